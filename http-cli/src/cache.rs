@@ -0,0 +1,489 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use anyhow::Result;
+use chrono::Utc;
+use colored::Colorize;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CACHE_CONTROL, ETAG, LAST_MODIFIED, VARY},
+    StatusCode, Version,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executor::{Executor, HttpResponse},
+    record::RecordedResponse,
+    sign::sha256_hex,
+    spec::RequestSpec,
+};
+
+// 本地http缓存，只缓存GET请求的200响应
+// 每个 方法+url+Authorization+执行器加上的凭据 保存一个文件，Vary 中列出的请求头不同时不使用缓存
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+// 缓存的响应以及重新验证需要的信息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    // 响应的 Vary 中列出的请求头，以及请求里这些请求头的值的哈希
+    #[serde(default)]
+    pub vary: Vec<String>,
+    #[serde(default)]
+    pub variant: String,
+    // 保存（或者最近一次重新验证）的时间，unix秒
+    pub stored_at: i64,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    // 在这段时间内直接使用缓存，不发请求，单位秒
+    #[serde(default)]
+    pub max_age: Option<u64>,
+    pub response: RecordedResponse,
+}
+
+// 一次使用缓存的请求的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    // 在 max-age 之内，直接使用缓存，没有发送请求
+    Fresh,
+    // 服务端返回304，使用缓存的响应体
+    Revalidated,
+    // 没有可用的缓存，使用服务端返回的响应
+    Miss,
+}
+
+// 响应头 Cache-Control 中和缓存有关的指令
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheControl {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub max_age: Option<u64>,
+}
+
+impl CacheControl {
+    pub fn parse(headers: &HeaderMap) -> Self {
+        Self::parse_values(headers.get_all(CACHE_CONTROL).iter().filter_map(|v| v.to_str().ok()))
+    }
+
+    // 请求里的 Cache-Control，例如 -H 'Cache-Control: no-store' 时不读也不写缓存
+    pub fn from_request(spec: &RequestSpec) -> Self {
+        Self::parse_values(
+            spec.headers
+                .iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case(CACHE_CONTROL.as_str()))
+                .map(|(_, v)| v.as_str()),
+        )
+    }
+
+    fn parse_values<'a>(values: impl Iterator<Item = &'a str>) -> Self {
+        let mut cc = Self::default();
+
+        let directives = values.flat_map(|v| v.split(',')).map(|d| d.trim().to_ascii_lowercase());
+        for directive in directives {
+            match directive.split_once('=') {
+                Some(("max-age", v)) => cc.max_age = v.trim_matches('"').parse().ok(),
+                None if directive == "no-store" => cc.no_store = true,
+                None if directive == "no-cache" => cc.no_cache = true,
+                _ => {}
+            }
+        }
+
+        cc
+    }
+
+    // no-cache 表示每次都要重新验证，相当于 max-age=0
+    fn fresh_for(&self) -> Option<u64> {
+        if self.no_cache {
+            Some(0)
+        } else {
+            self.max_age
+        }
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+}
+
+// 缓存文件的key，不同用户的响应分开保存
+// identity 是 Executor::identity，--oauth2 和 --sign 的凭据在发送时才加上，spec 里看不到
+fn key(spec: &RequestSpec, identity: &str) -> String {
+    let authorization = spec.header_value(AUTHORIZATION.as_str()).unwrap_or_default();
+    sha256_hex(format!("{}\n{}\n{}\n{}", spec.method, spec.url, authorization, identity).as_bytes())
+}
+
+// Vary 中列出的请求头的值，只保存哈希，不把请求头的内容写进缓存文件
+fn variant(spec: &RequestSpec, vary: &[String]) -> String {
+    let values: String = vary
+        .iter()
+        .map(|name| format!("{}:{}\n", name, spec.header_value(name).unwrap_or_default()))
+        .collect();
+    sha256_hex(values.as_bytes())
+}
+
+// 响应的 Vary 请求头，名字转成小写；Vary: * 时返回None，表示不能缓存
+fn vary(headers: &HeaderMap) -> Option<Vec<String>> {
+    let mut names: Vec<String> = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.iter().any(|name| name == "*") {
+        return None;
+    }
+    names.sort();
+    names.dedup();
+    Some(names)
+}
+
+impl CacheEntry {
+    // 响应可以缓存时返回缓存条目：状态码为200，没有 no-store、Vary: *，并且有 ETag、Last-Modified 或 max-age
+    pub fn new(spec: &RequestSpec, resp: &HttpResponse, now: i64) -> Option<Self> {
+        let cc = CacheControl::parse(&resp.headers);
+        if resp.status != StatusCode::OK || cc.no_store {
+            return None;
+        }
+        let vary = vary(&resp.headers)?;
+
+        let entry = Self {
+            url: spec.url.clone(),
+            variant: variant(spec, &vary),
+            vary,
+            stored_at: now,
+            etag: header_str(&resp.headers, ETAG),
+            last_modified: header_str(&resp.headers, LAST_MODIFIED),
+            max_age: cc.fresh_for(),
+            response: RecordedResponse::from_response(resp),
+        };
+
+        if entry.etag.is_none() && entry.last_modified.is_none() && entry.max_age.unwrap_or(0) == 0 {
+            return None;
+        }
+        Some(entry)
+    }
+
+    // 是否还在 max-age 之内
+    pub fn is_fresh(&self, now: i64) -> bool {
+        match self.max_age {
+            Some(max_age) => now - self.stored_at < max_age as i64,
+            None => false,
+        }
+    }
+
+    // 重新验证时要带上的条件请求头
+    pub fn conditional_headers(&self) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        if let Some(ref etag) = self.etag {
+            headers.push(("If-None-Match".to_string(), etag.clone()));
+        }
+        if let Some(ref last_modified) = self.last_modified {
+            headers.push(("If-Modified-Since".to_string(), last_modified.clone()));
+        }
+        headers
+    }
+
+    // 收到304后，用新的响应头更新缓存的有效期和校验信息
+    pub fn refresh(&mut self, headers: &HeaderMap, now: i64) {
+        self.stored_at = now;
+        if let Some(etag) = header_str(headers, ETAG) {
+            self.etag = Some(etag);
+        }
+        if let Some(last_modified) = header_str(headers, LAST_MODIFIED) {
+            self.last_modified = Some(last_modified);
+        }
+        if headers.contains_key(CACHE_CONTROL) {
+            self.max_age = CacheControl::parse(headers).fresh_for();
+        }
+    }
+
+    // 把缓存的响应还原成 HttpResponse
+    pub fn to_response(&self, version: Version, duration: Duration) -> Result<HttpResponse> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.response.headers.iter() {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+                headers.append(name, value);
+            }
+        }
+        let body = self.response.body_bytes()?;
+
+        Ok(HttpResponse {
+            version,
+            status: StatusCode::from_u16(self.response.status)?,
+            headers,
+            request_headers: HeaderMap::new(),
+            encoded_len: body.len(),
+            body,
+            decode_error: None,
+            request_len: 0,
+            request_sent_len: 0,
+            duration,
+        })
+    }
+}
+
+// 默认的缓存目录，例如 ~/.cache/http-cli
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("http-cli"))
+}
+
+impl Cache {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    // 缓存文件名是 方法+url+Authorization+identity 的sha256
+    fn path(&self, spec: &RequestSpec, identity: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key(spec, identity)))
+    }
+
+    // 读取缓存，文件损坏或者 Vary 中的请求头和缓存时不同时当作没有缓存
+    pub fn get(&self, spec: &RequestSpec, identity: &str) -> Option<CacheEntry> {
+        let content = fs::read_to_string(self.path(spec, identity)).ok()?;
+        serde_json::from_str::<CacheEntry>(&content)
+            .ok()
+            .filter(|entry| entry.url == spec.url && entry.variant == variant(spec, &entry.vary))
+    }
+
+    pub fn put(&self, spec: &RequestSpec, identity: &str, entry: &CacheEntry) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(spec, identity), serde_json::to_string_pretty(entry)?)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, spec: &RequestSpec, identity: &str) -> Result<()> {
+        let path = self.path(spec, identity);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    // 发送GET请求：max-age内直接使用缓存，否则带上条件请求头，304时使用缓存的响应体
+    pub async fn send(&self, executor: &Executor, spec: &RequestSpec) -> Result<(HttpResponse, CacheStatus)> {
+        let now = Utc::now().timestamp();
+        let identity = executor.identity(spec);
+        let entry = self.get(spec, &identity);
+
+        let mut conditional = spec.clone();
+        if let Some(ref entry) = entry {
+            if entry.is_fresh(now) {
+                return Ok((entry.to_response(Version::HTTP_11, Duration::ZERO)?, CacheStatus::Fresh));
+            }
+            for (name, value) in entry.conditional_headers() {
+                if conditional.header_value(&name).is_none() {
+                    conditional = conditional.header(&name, &value);
+                }
+            }
+        }
+
+        let resp = executor.send(&conditional).await?;
+
+        // 缓存写入失败不影响请求本身，只打印警告
+        if let Some(mut entry) = entry.filter(|_| resp.status == StatusCode::NOT_MODIFIED) {
+            entry.refresh(&resp.headers, now);
+            if let Err(e) = self.put(spec, &identity, &entry) {
+                eprintln!("{}", format!("warning: failed to write cache: {}", e).yellow());
+            }
+            let mut cached = entry.to_response(resp.version, resp.duration)?;
+            cached.request_headers = resp.request_headers;
+            return Ok((cached, CacheStatus::Revalidated));
+        }
+        if let Err(e) = self.store(spec, &identity, &resp, now) {
+            eprintln!("{}", format!("warning: failed to write cache: {}", e).yellow());
+        }
+
+        Ok((resp, CacheStatus::Miss))
+    }
+
+    // 按响应更新缓存：可以缓存时保存，不能缓存（例如 no-store）时删除旧的缓存
+    pub fn store(&self, spec: &RequestSpec, identity: &str, resp: &HttpResponse, now: i64) -> Result<()> {
+        match CacheEntry::new(spec, resp, now) {
+            Some(entry) => self.put(spec, identity, &entry),
+            None if resp.status == StatusCode::OK => self.remove(spec, identity),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&'static str, &'static str)], body: &str) -> HttpResponse {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers.iter() {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+
+        HttpResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::OK,
+            headers: map,
+            request_headers: HeaderMap::new(),
+            body: body.as_bytes().to_vec(),
+            encoded_len: body.len(),
+            decode_error: None,
+            request_len: 0,
+            request_sent_len: 0,
+            duration: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn cache_control_works() {
+        let resp = response(&[("cache-control", "public, max-age=60"), ("cache-control", "no-cache")], "");
+        assert_eq!(
+            CacheControl::parse(&resp.headers),
+            CacheControl {
+                no_store: false,
+                no_cache: true,
+                max_age: Some(60),
+            }
+        );
+        assert_eq!(CacheControl::parse(&resp.headers).fresh_for(), Some(0));
+        assert!(CacheControl::parse(&response(&[("cache-control", "No-Store")], "").headers).no_store);
+        let spec = RequestSpec::new("GET", "http://localhost/").header("cache-control", "max-age=0, no-store");
+        assert!(CacheControl::from_request(&spec).no_store);
+    }
+
+    #[test]
+    fn entry_works() {
+        let url = &RequestSpec::new("GET", "http://localhost:3000/users");
+        let resp = response(&[("etag", "\"v1\""), ("cache-control", "max-age=60"), ("content-type", "application/json")], "[]");
+
+        let mut entry = CacheEntry::new(url, &resp, 1000).unwrap();
+        assert!(entry.is_fresh(1059));
+        assert!(!entry.is_fresh(1060));
+        assert_eq!(entry.conditional_headers(), vec![("If-None-Match".to_string(), "\"v1\"".to_string())]);
+
+        // 304 带来新的有效期
+        let not_modified = response(&[("cache-control", "max-age=10"), ("etag", "\"v1\"")], "");
+        entry.refresh(&not_modified.headers, 2000);
+        assert!(entry.is_fresh(2009));
+        assert!(!entry.is_fresh(2010));
+
+        let cached = entry.to_response(Version::HTTP_11, Duration::ZERO).unwrap();
+        assert_eq!(cached.status, StatusCode::OK);
+        assert_eq!(cached.body, b"[]");
+        assert_eq!(cached.headers["content-type"], "application/json");
+
+        // 没有校验信息、no-store 或者非200的响应不缓存
+        assert!(CacheEntry::new(url, &response(&[], "x"), 0).is_none());
+        assert!(CacheEntry::new(url, &response(&[("etag", "\"a\""), ("cache-control", "no-store")], "x"), 0).is_none());
+        assert!(CacheEntry::new(url, &response(&[("etag", "\"a\""), ("vary", "*")], "x"), 0).is_none());
+        let mut not_found = response(&[("etag", "\"a\"")], "x");
+        not_found.status = StatusCode::NOT_FOUND;
+        assert!(CacheEntry::new(url, &not_found, 0).is_none());
+
+        // 只有 Last-Modified 时也可以重新验证
+        let entry = CacheEntry::new(url, &response(&[("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT")], "x"), 0).unwrap();
+        assert!(!entry.is_fresh(0));
+        assert_eq!(entry.conditional_headers()[0].0, "If-Modified-Since");
+    }
+
+    #[test]
+    fn store_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        let url = &RequestSpec::new("GET", "http://localhost:3000/users");
+
+        assert!(cache.get(url, "").is_none());
+        cache.store(url, "", &response(&[("etag", "\"v1\"")], "[1]"), 0).unwrap();
+        assert_eq!(cache.get(url, "").unwrap().response.body, "[1]");
+        assert!(cache.get(&RequestSpec::new("GET", "http://localhost:3000/other"), "").is_none());
+        // 方法和 Authorization 不同时分开缓存
+        assert!(cache.get(&RequestSpec::new("HEAD", &url.url), "").is_none());
+        assert!(cache.get(&url.clone().header("Authorization", "Bearer b"), "").is_none());
+
+        // Vary 中的请求头不同时不使用缓存
+        let json = url.clone().header("Accept", "application/json");
+        cache.store(&json, "", &response(&[("etag", "\"v1\""), ("vary", "Accept, Accept-Language")], "[1]"), 0).unwrap();
+        assert!(cache.get(&json, "").is_some());
+        assert!(cache.get(&json.clone().header("Accept-Language", "zh"), "").is_none());
+        assert!(cache.get(&url.clone().header("Accept", "text/html"), "").is_none());
+
+        // no-store 会删掉旧的缓存
+        cache.store(url, "", &response(&[("cache-control", "no-store")], "[2]"), 0).unwrap();
+        assert!(cache.get(url, "").is_none());
+    }
+
+    // 令牌接口按 client_id 发放令牌，/me 返回收到的 Authorization，可以缓存60秒
+    async fn server() -> std::net::SocketAddr {
+        use std::convert::Infallible;
+        use hyper::{
+            service::{make_service_fn, service_fn},
+            Body, Request, Response, Server,
+        };
+
+        let make = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+                let auth = req.headers().get("authorization").map(|v| v.to_str().unwrap().to_string());
+                let resp = match req.uri().path() {
+                    "/token" => {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let body = String::from_utf8_lossy(&body);
+                        let client_id = body.split('&').find_map(|kv| kv.strip_prefix("client_id=")).unwrap_or_default();
+                        Response::new(Body::from(format!(r#"{{"access_token": "{}", "token_type": "Bearer"}}"#, client_id)))
+                    }
+                    _ => Response::builder()
+                        .header("cache-control", "max-age=60")
+                        .body(Body::from(auth.unwrap_or_default()))
+                        .unwrap(),
+                };
+                Ok::<_, Infallible>(resp)
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn identity_works() {
+        use std::sync::Arc;
+        use crate::oauth2::{ClientAuth, Grant, OAuth2, OAuth2Config};
+
+        let addr = server().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let spec = RequestSpec::new("GET", &format!("http://{}/me", addr));
+
+        let executor = |client_id: &str| {
+            let config = OAuth2Config {
+                grant: Grant::ClientCredentials,
+                token_url: format!("http://{}/token", addr),
+                client_id: client_id.into(),
+                client_secret: None,
+                scope: None,
+                refresh_token: None,
+                device_authorization_url: None,
+                client_auth: ClientAuth::Body,
+            };
+            Executor::new(reqwest::Client::new()).oauth2(Some(Arc::new(OAuth2::new(config).unwrap())))
+        };
+
+        // 两个 --oauth2 配置请求同一个url，不能共用缓存
+        let (resp, status) = cache.send(&executor("a"), &spec).await.unwrap();
+        assert_eq!((resp.text(), status), ("Bearer a".to_string(), CacheStatus::Miss));
+        let (resp, status) = cache.send(&executor("b"), &spec).await.unwrap();
+        assert_eq!((resp.text(), status), ("Bearer b".to_string(), CacheStatus::Miss));
+        let (resp, status) = cache.send(&executor("a"), &spec).await.unwrap();
+        assert_eq!((resp.text(), status), ("Bearer a".to_string(), CacheStatus::Fresh));
+
+        // 不带凭据的请求也不能用带凭据的缓存
+        let (resp, status) = cache.send(&Executor::new(reqwest::Client::new()), &spec).await.unwrap();
+        assert_eq!((resp.text(), status), (String::new(), CacheStatus::Miss));
+    }
+}
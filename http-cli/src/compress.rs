@@ -0,0 +1,95 @@
+use std::io::{Read, Write};
+use anyhow::{anyhow, Result};
+use flate2::{
+    read::{DeflateDecoder, GzDecoder, ZlibDecoder},
+    write::GzEncoder,
+    Compression,
+};
+
+// 客户端声明支持的响应压缩格式，解压由下面的 decode 显式完成，而不是交给reqwest
+pub const ACCEPT_ENCODING: &str = "gzip, deflate, br, zstd";
+
+// 按 Content-Encoding 解压响应体
+// 多个编码时（例如 "gzip, br"）按声明的逆序依次解压
+pub fn decode(encoding: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let mut body = data.to_vec();
+
+    for coding in encoding.split(',').map(|c| c.trim().to_ascii_lowercase()).rev() {
+        body = match coding.as_str() {
+            "" | "identity" => body,
+            "gzip" | "x-gzip" => read_all(GzDecoder::new(&body[..]))?,
+            // 规范里的deflate是zlib格式，但有些服务端直接返回裸deflate，这里两种都兼容
+            "deflate" => read_all(ZlibDecoder::new(&body[..]))
+                .or_else(|_| read_all(DeflateDecoder::new(&body[..])))?,
+            "br" => read_all(brotli::Decompressor::new(&body[..], 4096))?,
+            "zstd" => zstd::stream::decode_all(&body[..])?,
+            other => return Err(anyhow!("unsupported content-encoding: {}", other)),
+        };
+    }
+
+    Ok(body)
+}
+
+// 解压响应体，不支持的编码或者解压失败时返回原始数据和失败原因，和 curl、httpie 一样显示原始数据
+pub fn decode_or_raw(encoding: &str, data: &[u8]) -> (Vec<u8>, Option<String>) {
+    match decode(encoding, data) {
+        Ok(body) => (body, None),
+        Err(e) => (data.to_vec(), Some(format!("cannot decode content-encoding {}: {:#}", encoding, e))),
+    }
+}
+
+// gzip压缩请求体
+pub fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+
+    Ok(encoder.finish()?)
+}
+
+fn read_all(mut reader: impl Read) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_works() {
+        let data = b"hello http-cli".repeat(10);
+
+        let gz = gzip(&data).unwrap();
+        assert!(gz.len() < data.len());
+        assert_eq!(decode("gzip", &gz).unwrap(), data);
+
+        let mut br = Vec::new();
+        brotli::CompressorWriter::new(&mut br, 4096, 5, 22).write_all(&data).unwrap();
+        assert_eq!(decode("br", &br).unwrap(), data);
+
+        let zs = zstd::stream::encode_all(&data[..], 0).unwrap();
+        assert_eq!(decode("zstd", &zs).unwrap(), data);
+
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(&data).unwrap();
+        assert_eq!(decode("deflate", &zlib.finish().unwrap()).unwrap(), data);
+
+        // 多层编码按逆序解压
+        let both = zstd::stream::encode_all(&gz[..], 0).unwrap();
+        assert_eq!(decode("gzip, zstd", &both).unwrap(), data);
+
+        assert_eq!(decode("identity", &data).unwrap(), data);
+        assert!(decode("compress", &data).is_err());
+
+        // 响应体解压失败时退回原始数据
+        let (body, err) = decode_or_raw("compress", &data);
+        assert_eq!(body, data);
+        assert!(err.unwrap().contains("unsupported content-encoding: compress"));
+        let (body, err) = decode_or_raw("gzip", &data);
+        assert_eq!(body, data);
+        assert!(err.is_some());
+        assert_eq!(decode_or_raw("gzip", &gz), (data, None));
+    }
+}
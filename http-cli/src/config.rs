@@ -0,0 +1,304 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, time::Duration};
+use anyhow::{anyhow, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, ClientBuilder, Proxy,
+};
+use serde::Deserialize;
+
+use crate::{
+    direct::DirectClient,
+    formatter::{theme_names, DEFAULT_THEME, NO_THEME},
+    oauth2::OAuth2Config,
+    protocol::Protocol,
+    sign::SignerConfig,
+};
+#[cfg(unix)]
+use crate::unix::UnixClient;
+
+// 最终生效的配置，优先级从低到高：内置默认值 < 配置文件 < 环境变量 < 命令行参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    // 默认请求头
+    pub headers: Vec<(String, String)>,
+    // 请求超时时间，单位秒，None 表示不超时
+    pub timeout: Option<u64>,
+    // 语法高亮的主题，none 表示不高亮
+    pub theme: String,
+    // 是否校验https证书
+    pub verify: bool,
+    pub proxy: Option<String>,
+    // 按名字配置的请求签名，通过 --sign 选择
+    pub signers: BTreeMap<String, SignerConfig>,
+    // 按名字配置的OAuth2令牌获取方式，通过 --oauth2 选择
+    pub oauth2: BTreeMap<String, OAuth2Config>,
+    // http协议版本，只能通过命令行参数指定
+    pub protocol: Protocol,
+}
+
+// 配置文件的内容，没有写的字段保持原来的值
+// 例如 ~/.config/http-cli/config.toml：
+// timeout = 30
+// theme = "Solarized (dark)"
+// verify = true
+// proxy = "http://127.0.0.1:7890"
+// [headers]
+// X-POWERED-BY = ""   # 值为空时去掉这个默认请求头
+// [signers.gateway]
+// type = "hmac"
+// secret = "${GATEWAY_SECRET}"
+// [oauth2.api]
+// token_url = "https://auth.example.com/oauth/token"
+// client_id = "cli"
+// client_secret = "${API_CLIENT_SECRET}"
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    headers: Option<toml::value::Table>,
+    timeout: Option<u64>,
+    theme: Option<String>,
+    verify: Option<bool>,
+    proxy: Option<String>,
+    #[serde(default)]
+    signers: BTreeMap<String, SignerConfig>,
+    #[serde(default)]
+    oauth2: BTreeMap<String, OAuth2Config>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            // 添加一些默认的请求头
+            headers: vec![
+                ("X-POWERED-BY".to_string(), "Rust".to_string()),
+                ("User-Agent".to_string(), "Rust Httpie".to_string()),
+            ],
+            timeout: None,
+            theme: DEFAULT_THEME.to_string(),
+            verify: true,
+            proxy: None,
+            signers: BTreeMap::new(),
+            oauth2: BTreeMap::new(),
+            protocol: Protocol::Auto,
+        }
+    }
+}
+
+// 默认的配置文件，例如 ~/.config/http-cli/config.toml
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("http-cli").join("config.toml"))
+}
+
+fn parse_bool(name: &str, s: &str) -> Result<bool> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(anyhow!("invalid value {} for {}, expected true or false", s, name)),
+    }
+}
+
+impl Settings {
+    // 设置默认请求头，同名（不区分大小写）的会被覆盖，值为空时删除
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        if !value.is_empty() {
+            self.headers.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    // 合并配置文件，文件不存在时忽略
+    pub fn merge_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(path)?;
+        let file: ConfigFile = toml::from_str(&content).map_err(|e| anyhow!("invalid config {}: {}", path.display(), e))?;
+
+        for (name, value) in file.headers.unwrap_or_default() {
+            let value = match value {
+                toml::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            self.set_header(&name, &value);
+        }
+        if file.timeout.is_some() {
+            self.timeout = file.timeout;
+        }
+        if let Some(theme) = file.theme {
+            self.theme = theme;
+        }
+        if let Some(verify) = file.verify {
+            self.verify = verify;
+        }
+        if file.proxy.is_some() {
+            self.proxy = file.proxy;
+        }
+        self.signers.extend(file.signers);
+        self.oauth2.extend(file.oauth2);
+
+        Ok(())
+    }
+
+    // 合并环境变量 HTTP_CLI_TIMEOUT、HTTP_CLI_THEME、HTTP_CLI_VERIFY、HTTP_CLI_PROXY
+    // var 用来读取环境变量，方便测试时替换
+    pub fn merge_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(timeout) = var("HTTP_CLI_TIMEOUT") {
+            let timeout = timeout
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid value {} for HTTP_CLI_TIMEOUT, expected seconds", timeout))?;
+            self.timeout = Some(timeout);
+        }
+        if let Some(theme) = var("HTTP_CLI_THEME") {
+            self.theme = theme;
+        }
+        if let Some(verify) = var("HTTP_CLI_VERIFY") {
+            self.verify = parse_bool("HTTP_CLI_VERIFY", &verify)?;
+        }
+        if let Some(proxy) = var("HTTP_CLI_PROXY") {
+            self.proxy = Some(proxy);
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let themes = theme_names();
+        if self.theme != NO_THEME && !themes.contains(&self.theme) {
+            return Err(anyhow!(
+                "unknown theme {}, available themes: {}, {}",
+                self.theme,
+                NO_THEME,
+                themes.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn default_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.iter() {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("invalid header name {}: {}", name, e))?;
+            headers.insert(name, HeaderValue::from_str(value)?);
+        }
+
+        Ok(headers)
+    }
+
+    // 按配置创建http请求客户端
+    pub fn build_client(&self) -> Result<Client> {
+        Ok(self.protocol.apply(self.client_builder()?)?.build()?)
+    }
+
+    // 获取 --oauth2 令牌的客户端，令牌接口和API可能不在同一个服务上，不使用 --http2 等指定的协议
+    pub fn build_token_client(&self) -> Result<Client> {
+        Ok(self.client_builder()?.build()?)
+    }
+
+    fn client_builder(&self) -> Result<ClientBuilder> {
+        let mut builder = Client::builder()
+            .default_headers(self.default_headers()?)
+            .danger_accept_invalid_certs(!self.verify);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        if let Some(ref proxy) = self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str()).map_err(|e| anyhow!("invalid proxy {}: {}", proxy, e))?);
+        }
+
+        Ok(builder)
+    }
+
+    // 按配置创建发送 --expect-100 请求的客户端，代理对它不生效
+    pub fn build_direct_client(&self) -> Result<DirectClient> {
+        let mut client = DirectClient::new().default_headers(self.default_headers()?).verify(self.verify);
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(Duration::from_secs(timeout));
+        }
+
+        Ok(client)
+    }
+
+    // 按配置创建通过Unix domain socket发送请求的客户端，代理和证书校验对它不生效
+    #[cfg(unix)]
+    pub fn build_unix_client(&self, path: impl AsRef<Path>) -> Result<UnixClient> {
+        let mut client = UnixClient::new(path).default_headers(self.default_headers()?);
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(Duration::from_secs(timeout));
+        }
+
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn precedence_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "timeout = 30\ntheme = \"InspiredGitHub\"\nverify = false\n\n[headers]\nX-POWERED-BY = \"\"\nuser-agent = \"my-agent\"\nX-Team = \"infra\"\n",
+        )
+        .unwrap();
+
+        let mut settings = Settings::default();
+        settings.merge_file(&path).unwrap();
+        assert_eq!(settings.timeout, Some(30));
+        assert_eq!(settings.theme, "InspiredGitHub");
+        assert!(!settings.verify);
+        assert_eq!(
+            settings.headers,
+            vec![
+                ("X-Team".to_string(), "infra".to_string()),
+                ("user-agent".to_string(), "my-agent".to_string()),
+            ]
+        );
+
+        // 环境变量的优先级高于配置文件
+        let env: HashMap<&str, &str> = [("HTTP_CLI_TIMEOUT", "5"), ("HTTP_CLI_VERIFY", "yes")].into_iter().collect();
+        settings.merge_env(|k| env.get(k).map(|v| v.to_string())).unwrap();
+        assert_eq!(settings.timeout, Some(5));
+        assert!(settings.verify);
+        assert_eq!(settings.theme, "InspiredGitHub");
+        assert!(settings.validate().is_ok());
+
+        assert!(settings.merge_env(|k| (k == "HTTP_CLI_TIMEOUT").then(|| "abc".to_string())).is_err());
+    }
+
+    #[test]
+    fn merge_file_works() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // 文件不存在时保持默认值
+        let mut settings = Settings::default();
+        settings.merge_file(dir.path().join("missing.toml")).unwrap();
+        assert_eq!(settings, Settings::default());
+
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "timeout = \"abc\"\n").unwrap();
+        assert!(settings.merge_file(&path).is_err());
+
+        fs::write(&path, "unknown = 1\n").unwrap();
+        assert!(settings.merge_file(&path).is_err());
+
+        fs::write(&path, "[signers.minio]\ntype = \"aws-sigv4\"\naccess_key = \"a\"\nsecret_key = \"b\"\n").unwrap();
+        settings.merge_file(&path).unwrap();
+        assert!(matches!(settings.signers.get("minio"), Some(SignerConfig::AwsSigv4(_))));
+
+        fs::write(&path, "[oauth2.api]\ngrant = \"device-code\"\ntoken_url = \"https://auth/token\"\nclient_id = \"cli\"\n").unwrap();
+        settings.merge_file(&path).unwrap();
+        assert_eq!(settings.oauth2["api"].grant, crate::oauth2::Grant::DeviceCode);
+        assert_eq!(settings.signers.len(), 1);
+
+        settings.theme = "no-such-theme".to_string();
+        assert!(settings.validate().is_err());
+    }
+}
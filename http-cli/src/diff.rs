@@ -0,0 +1,196 @@
+use std::{collections::BTreeSet, io::{self, Write}};
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use crate::executor::HttpResponse;
+
+// 一处差异
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Added(String, Value),
+    Removed(String, Value),
+    Changed(String, Value, Value),
+}
+
+// 参与对比的响应快照，从文件读取时没有状态码和响应头
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    pub status: Option<Value>,
+    pub headers: Option<Value>,
+    pub body: Value,
+}
+
+impl Snapshot {
+    pub fn from_response(resp: &HttpResponse) -> Self {
+        let mut headers = Map::new();
+        for (name, value) in resp.headers.iter() {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            // 同名的响应头合并成一个值
+            match headers.get_mut(name.as_str()) {
+                Some(Value::String(v)) => {
+                    v.push_str(", ");
+                    v.push_str(&value);
+                }
+                _ => {
+                    headers.insert(name.to_string(), Value::String(value));
+                }
+            }
+        }
+
+        Self {
+            status: Some(Value::from(resp.status.as_u16())),
+            headers: Some(Value::Object(headers)),
+            body: parse_body(&resp.text()),
+        }
+    }
+
+    // 只有响应体的快照，例如保存下来的响应文件
+    pub fn from_body(text: &str) -> Self {
+        Self {
+            body: parse_body(text),
+            ..Default::default()
+        }
+    }
+}
+
+// 响应体能按json解析就按json对比（忽略key的顺序），否则按字符串对比
+fn parse_body(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string()))
+}
+
+// 路径拆成段：body.items[0].id => ["body", "items", "0", "id"]，paginate 按同样的规则取值
+pub(crate) fn segments(path: &str) -> Vec<&str> {
+    path.split(['.', '[', ']'])
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+// 判断路径是否被忽略，忽略规则匹配路径本身或者它的父路径，* 匹配任意一段
+fn is_ignored(path: &str, ignore: &[String]) -> bool {
+    let path = segments(path);
+
+    ignore.iter().any(|pattern| {
+        let pattern = segments(pattern);
+        pattern.len() <= path.len()
+            && pattern.iter().zip(path.iter()).all(|(p, s)| *p == "*" || p == s)
+    })
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// 递归对比两个json值，对象按key对比（忽略顺序），数组按下标对比
+pub fn diff_values(path: &str, a: &Value, b: &Value, ignore: &[String], changes: &mut Vec<Change>) {
+    if is_ignored(path, ignore) {
+        return;
+    }
+
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let path = child_path(path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(va), Some(vb)) => diff_values(&path, va, vb, ignore, changes),
+                    (Some(va), None) if !is_ignored(&path, ignore) => changes.push(Change::Removed(path, va.clone())),
+                    (None, Some(vb)) if !is_ignored(&path, ignore) => changes.push(Change::Added(path, vb.clone())),
+                    _ => {}
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let path = format!("{}[{}]", path, i);
+                match (a.get(i), b.get(i)) {
+                    (Some(va), Some(vb)) => diff_values(&path, va, vb, ignore, changes),
+                    (Some(va), None) if !is_ignored(&path, ignore) => changes.push(Change::Removed(path, va.clone())),
+                    (None, Some(vb)) if !is_ignored(&path, ignore) => changes.push(Change::Added(path, vb.clone())),
+                    _ => {}
+                }
+            }
+        }
+        _ if a != b => changes.push(Change::Changed(path.to_string(), a.clone(), b.clone())),
+        _ => {}
+    }
+}
+
+// 对比状态码、响应头和响应体，只有两边都有的部分才参与对比
+pub fn diff_snapshots(a: &Snapshot, b: &Snapshot, ignore: &[String]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    if let (Some(sa), Some(sb)) = (&a.status, &b.status) {
+        diff_values("status", sa, sb, ignore, &mut changes);
+    }
+    if let (Some(ha), Some(hb)) = (&a.headers, &b.headers) {
+        diff_values("headers", ha, hb, ignore, &mut changes);
+    }
+    diff_values("body", &a.body, &b.body, ignore, &mut changes);
+
+    changes
+}
+
+// 打印差异：- 只在A中，+ 只在B中，~ 两边不同
+pub fn write_changes(out: &mut dyn Write, changes: &[Change]) -> io::Result<()> {
+    for change in changes.iter() {
+        match change {
+            Change::Removed(path, v) => writeln!(out, "{}", format!("- {}: {}", path, v).red())?,
+            Change::Added(path, v) => writeln!(out, "{}", format!("+ {}: {}", path, v).green())?,
+            Change::Changed(path, a, b) => writeln!(
+                out,
+                "{} {}: {} {} {}",
+                "~".yellow(),
+                path.yellow(),
+                a.to_string().red(),
+                "->".yellow(),
+                b.to_string().green()
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn diff_values_works() {
+        // key的顺序不影响结果
+        let a = json!({"id": 1, "name": "a", "tags": ["x", "y"], "meta": {"ts": 1}});
+        let b = json!({"meta": {"ts": 2}, "tags": ["x"], "name": "b", "id": 1, "new": true});
+
+        let mut changes = Vec::new();
+        diff_values("body", &a, &b, &[], &mut changes);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Changed("body.meta.ts".into(), json!(1), json!(2)),
+                Change::Changed("body.name".into(), json!("a"), json!("b")),
+                Change::Added("body.new".into(), json!(true)),
+                Change::Removed("body.tags[1]".into(), json!("y")),
+            ]
+        );
+
+        let mut changes = Vec::new();
+        diff_values("body", &json!({"b": 1, "a": 2}), &json!({"a": 2, "b": 1}), &[], &mut changes);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn is_ignored_works() {
+        let ignore = vec!["headers.date".to_string(), "body.items[*].updated_at".to_string()];
+
+        assert!(is_ignored("headers.date", &ignore));
+        assert!(is_ignored("body.items[3].updated_at", &ignore));
+        assert!(is_ignored("body.items[3].updated_at.nanos", &ignore));
+        assert!(!is_ignored("body.items[3].id", &ignore));
+        assert!(!is_ignored("headers.server", &ignore));
+    }
+}
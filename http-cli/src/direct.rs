@@ -0,0 +1,410 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures_core::Stream;
+use hyper::{client::conn, Body as HyperBody, Request};
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    Url,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    sync::oneshot,
+};
+use tokio_rustls::{
+    rustls::{
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, Error as TlsError, OwnedTrustAnchor, RootCertStore, ServerName,
+    },
+    TlsConnector,
+};
+
+use crate::{
+    executor::{HttpResponse, Prepared, PreparedBody},
+    protocol::Protocol,
+    sign::Signer,
+    spec::{host, path_and_query, RequestSpec},
+    upload::UploadStream,
+};
+
+// 带 Expect: 100-continue 时，服务端一直不回复 100 Continue 的话，最多等这么久再上传请求体
+pub const EXPECT_CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// 不经过 reqwest 的连接池，每个请求单独建立连接，用 hyper 的 conn 接口发送
+// reqwest 拿不到 100 Continue 的中间响应，--expect-100 的请求通过它发送；只支持 HTTP/1.1，不经过代理
+#[derive(Debug, Clone)]
+pub struct DirectClient {
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+    // 是否校验https证书，和 reqwest 一样使用内置的根证书
+    verify: bool,
+}
+
+impl Default for DirectClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DirectClient {
+    pub fn new() -> Self {
+        Self {
+            headers: HeaderMap::new(),
+            timeout: None,
+            verify: true,
+        }
+    }
+
+    // 默认请求头，请求里有同名的请求头时不会发送
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    // 发送请求，读取并解压响应，signer 会在发送前对请求签名
+    pub async fn execute_with(&self, spec: &RequestSpec, signer: Option<&dyn Signer>) -> Result<HttpResponse> {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.send(spec, signer))
+                .await
+                .map_err(|_| anyhow!("request to {} timed out after {:?}", spec.url, timeout))?,
+            None => self.send(spec, signer).await,
+        }
+    }
+
+    async fn send(&self, spec: &RequestSpec, signer: Option<&dyn Signer>) -> Result<HttpResponse> {
+        if !matches!(spec.protocol, Protocol::Auto | Protocol::Http1) {
+            return Err(anyhow!("{} cannot be used with Expect: 100-continue, only HTTP/1.1 is supported", spec.protocol));
+        }
+        let url = Url::parse(&spec.url).map_err(|e| anyhow!("invalid url {}: {}", spec.url, e))?;
+        let mut prepared = Prepared::new(spec)?;
+        prepared.sign(&spec.url, signer)?;
+
+        // IPv6 地址的 host_str 带方括号，连接时要去掉
+        let host = url.host_str().ok_or_else(|| anyhow!("url {} has no host", url))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = url.port_or_known_default().ok_or_else(|| anyhow!("url {} has no port", url))?;
+
+        let start = Instant::now();
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| anyhow!("failed to connect to {}:{}: {}", host, port, e))?;
+        match url.scheme() {
+            "http" => send_conn(tcp, &prepared, &url, &self.headers, spec.expect_continue, start).await,
+            "https" => {
+                let name = ServerName::try_from(host).map_err(|e| anyhow!("invalid server name {}: {}", host, e))?;
+                let tls = TlsConnector::from(self.tls_config())
+                    .connect(name, tcp)
+                    .await
+                    .map_err(|e| anyhow!("TLS handshake with {} failed: {}", host, e))?;
+                send_conn(tls, &prepared, &url, &self.headers, spec.expect_continue, start).await
+            }
+            scheme => Err(anyhow!("unsupported scheme {}: {}", scheme, url)),
+        }
+    }
+
+    // ALPN 只提供 http/1.1
+    fn tls_config(&self) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+        }));
+        let mut config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        if !self.verify {
+            config.dangerous().set_certificate_verifier(Arc::new(NoVerify));
+        }
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Arc::new(config)
+    }
+}
+
+// verify = false 时不校验证书，和 reqwest 的 danger_accept_invalid_certs 一样
+struct NoVerify;
+
+impl ServerCertVerifier for NoVerify {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+// 在已经建立的连接上用 HTTP/1.1 发送一个请求，Unix domain socket 也走这里
+// expect 为true并且有请求体时发送 Expect: 100-continue，等服务端确认后再上传请求体
+pub(crate) async fn send_conn<S>(
+    stream: S,
+    prepared: &Prepared,
+    url: &Url,
+    defaults: &HeaderMap,
+    expect: bool,
+    start: Instant,
+) -> Result<HttpResponse>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let expect = expect && prepared.body.is_some();
+    let (tx, rx) = oneshot::channel();
+    // 请求体是否开始发送了，服务端提前返回最终响应时没有发送
+    let sending = Arc::new(AtomicBool::new(false));
+
+    let body = match prepared.body {
+        Some(ref body) if expect => {
+            let source = match body {
+                PreparedBody::Bytes(bytes) => Source::Bytes(Some(Bytes::from(bytes.clone()))),
+                PreparedBody::Upload(upload) => Source::Upload(upload.stream()?),
+            };
+            HyperBody::wrap_stream(Delayed::new(rx, source, sending.clone()))
+        }
+        Some(PreparedBody::Bytes(ref bytes)) => HyperBody::from(bytes.clone()),
+        Some(PreparedBody::Upload(ref upload)) => HyperBody::wrap_stream(upload.stream()?),
+        None => HyperBody::empty(),
+    };
+    let mut req = Request::builder()
+        .method(prepared.method.clone())
+        .uri(path_and_query(url))
+        .body(body)?;
+
+    let headers = req.headers_mut();
+    if !prepared.headers.contains_key(header::HOST) {
+        headers.insert(header::HOST, HeaderValue::from_str(&host(url)?)?);
+    }
+    for (name, value) in defaults.iter() {
+        if !prepared.headers.contains_key(name) {
+            headers.append(name, value.clone());
+        }
+    }
+    for (name, value) in prepared.headers.iter() {
+        headers.append(name, value.clone());
+    }
+    if expect {
+        headers.insert(header::EXPECT, HeaderValue::from_static("100-continue"));
+    }
+
+    let sent = req.headers().clone();
+
+    let stream = Watch {
+        inner: stream,
+        head: Vec::new(),
+        tx: if expect { Some(tx) } else { None },
+    };
+    let (mut sender, connection) = conn::handshake(stream).await?;
+    // 连接由单独的任务驱动，读完响应后直接断开，服务端提前拒绝时还有没发送的请求体
+    let connection = tokio::spawn(connection);
+
+    let resp = sender.send_request(req).await?;
+    let (parts, body) = resp.into_parts();
+    let raw = hyper::body::to_bytes(body).await;
+    connection.abort();
+
+    let mut resp = prepared.response(sent, parts.version, parts.status, parts.headers, &raw?, start.elapsed())?;
+    if expect && !sending.load(Ordering::SeqCst) {
+        resp.request_sent_len = 0;
+    }
+    Ok(resp)
+}
+
+// 包装连接，查看服务端最先返回的状态码是不是 100 Continue，数据原样交给 hyper
+// hyper 会跳过 1xx 的中间响应，所以要在这里自己判断
+struct Watch<S> {
+    inner: S,
+    head: Vec<u8>,
+    // 收到状态码后发送是否是 100，只发送一次
+    tx: Option<oneshot::Sender<bool>>,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Watch<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let (Poll::Ready(Ok(())), Some(_)) = (&poll, &this.tx) {
+            let read = &buf.filled()[filled..];
+            this.head.extend_from_slice(read);
+            // 状态行 "HTTP/1.1 100 Continue" 中状态码是第9到12个字节，没读到这么多连接就关闭了也算作拒绝
+            if this.head.len() >= 12 || read.is_empty() {
+                let is_continue = this.head.get(9..12) == Some(&b"100"[..]);
+                if let Some(tx) = this.tx.take() {
+                    let _ = tx.send(is_continue);
+                }
+            }
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Watch<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+enum Source {
+    Bytes(Option<Bytes>),
+    Upload(UploadStream),
+}
+
+enum State {
+    Waiting(Pin<Box<dyn Future<Output = bool> + Send>>),
+    Sending,
+    // 服务端已经返回了最终响应，请求体不再发送，连接在读完响应后断开
+    Skipped,
+}
+
+// 等服务端确认后才开始发送的请求体
+// 收到 100 Continue 或者等待超时后发送，服务端直接返回最终响应（例如 401、413）时不发送
+struct Delayed {
+    state: State,
+    source: Source,
+    sending: Arc<AtomicBool>,
+}
+
+impl Delayed {
+    fn new(rx: oneshot::Receiver<bool>, source: Source, sending: Arc<AtomicBool>) -> Self {
+        let wait = async move {
+            match tokio::time::timeout(EXPECT_CONTINUE_TIMEOUT, rx).await {
+                Ok(Ok(is_continue)) => is_continue,
+                // 连接已经断开
+                Ok(Err(_)) => false,
+                // 服务端不支持 100-continue，和curl一样超时后直接发送
+                Err(_) => true,
+            }
+        };
+        Self {
+            state: State::Waiting(Box::pin(wait)),
+            source,
+            sending,
+        }
+    }
+}
+
+impl Stream for Delayed {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let State::Waiting(ref mut wait) = this.state {
+            match wait.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(true) => {
+                    this.sending.store(true, Ordering::SeqCst);
+                    this.state = State::Sending;
+                }
+                Poll::Ready(false) => this.state = State::Skipped,
+            }
+        }
+
+        match (&this.state, &mut this.source) {
+            (State::Skipped, _) => Poll::Pending,
+            (_, Source::Bytes(bytes)) => Poll::Ready(bytes.take().map(Ok)),
+            (_, Source::Upload(upload)) => Pin::new(upload).poll_next(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use crate::spec::Body;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Response, Server, StatusCode,
+    };
+
+    // /upload 读取请求体后原样返回（hyper 服务端在读取请求体时才回复 100 Continue）
+    // 其它路径不读取请求体，直接返回401
+    async fn server() -> std::net::SocketAddr {
+        let make = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req: Request<HyperBody>| async move {
+                let expect = req.headers().get(header::EXPECT).is_some();
+                let resp = match req.uri().path() {
+                    "/upload" => {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        Response::new(HyperBody::from(format!("{} {}", expect, String::from_utf8_lossy(&body))))
+                    }
+                    _ => {
+                        let mut resp = Response::new(HyperBody::from("login first"));
+                        *resp.status_mut() = StatusCode::UNAUTHORIZED;
+                        resp
+                    }
+                };
+                Ok::<_, Infallible>(resp)
+            }))
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn expect_continue_works() {
+        let addr = server().await;
+        let client = DirectClient::new().timeout(Duration::from_secs(5));
+        let spec = |path: &str| RequestSpec::post(&format!("http://{}{}", addr, path)).body(Body::Text("hello".into())).expect_continue(true);
+
+        // 收到 100 Continue 后马上发送请求体，不用等到超时
+        let start = Instant::now();
+        let resp = client.execute_with(&spec("/upload"), None).await.unwrap();
+        assert_eq!(resp.text(), "true hello");
+        assert_eq!(resp.request_headers.get(header::EXPECT).unwrap(), "100-continue");
+        assert_eq!(resp.request_sent_len, 5);
+        assert!(start.elapsed() < EXPECT_CONTINUE_TIMEOUT, "{:?}", start.elapsed());
+
+        // 服务端直接拒绝时不发送请求体
+        let resp = client.execute_with(&spec("/private"), None).await.unwrap();
+        assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.text(), "login first");
+        assert_eq!(resp.request_sent_len, 0);
+
+        // 没有开启时不带 Expect
+        let resp = client.execute_with(&spec("/upload").expect_continue(false), None).await.unwrap();
+        assert_eq!(resp.text(), "false hello");
+    }
+}
@@ -0,0 +1,368 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+use anyhow::{anyhow, Result};
+use mime::Mime;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Client, Method, StatusCode, Url, Version,
+};
+use serde_json::Value;
+
+#[cfg(unix)]
+use crate::unix::UnixClient;
+use crate::{
+    compress::{self, gzip},
+    oauth2::OAuth2,
+    protocol::Protocol,
+    sign::{SignRequest, Signer},
+    direct::DirectClient,
+    spec::{Body, RequestSpec},
+    upload::Upload,
+};
+
+// 读取完并且已经解压好的响应
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub version: Version,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    // 实际发送的请求头，包括默认请求头、签名和认证信息
+    pub request_headers: HeaderMap,
+    // 解压后的响应体
+    pub body: Vec<u8>,
+    // 响应体传输时（解压前）的大小
+    pub encoded_len: usize,
+    // 解压失败的原因，这时 body 是原始数据，由调用方决定是否提示
+    pub decode_error: Option<String>,
+    // 请求体原始大小，以及实际发送的大小（压缩后）
+    pub request_len: usize,
+    pub request_sent_len: usize,
+    // 从发出请求到读完响应体的耗时
+    pub duration: Duration,
+}
+
+impl HttpResponse {
+    pub fn content_encoding(&self) -> Option<&str> {
+        self.headers
+            .get(header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+    }
+
+    // 将服务器返回的 content-type 解析成 Mime 类型，无法解析时返回None
+    pub fn content_type(&self) -> Option<Mime> {
+        self.headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json(&self) -> Result<Value> {
+        serde_json::from_slice(&self.body).map_err(|e| anyhow!("response is not a valid json: {}", e))
+    }
+}
+
+// 发送前准备好的请求：补全了默认的请求头，请求体已经按需压缩
+// reqwest 和 Unix domain socket 两种发送方式共用
+#[derive(Debug, Clone)]
+pub(crate) struct Prepared {
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Option<PreparedBody>,
+    pub request_len: usize,
+    pub request_sent_len: usize,
+}
+
+// 内存中的请求体，或者边读边发送的流式请求体
+#[derive(Debug, Clone)]
+pub(crate) enum PreparedBody {
+    Bytes(Vec<u8>),
+    Upload(Upload),
+}
+
+impl Prepared {
+    pub fn new(spec: &RequestSpec) -> Result<Self> {
+        let method = Method::from_bytes(spec.method.as_bytes())
+            .map_err(|_| anyhow!("invalid http method {}", spec.method))?;
+        let mut headers = HeaderMap::new();
+
+        // 显式声明支持的压缩格式，响应由 compress::decode 解压
+        if spec.header_value(header::ACCEPT_ENCODING.as_str()).is_none() {
+            headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(compress::ACCEPT_ENCODING));
+        }
+        for (name, value) in spec.headers.iter() {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("invalid header name {}: {}", name, e))?;
+            headers.append(name, HeaderValue::from_str(value)?);
+        }
+        for (name, value) in spec.default_headers.iter() {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("invalid header name {}: {}", name, e))?;
+            if !headers.contains_key(&name) {
+                headers.insert(name, HeaderValue::from_str(value)?);
+            }
+        }
+
+        // 文件和multipart不读进内存，边读边发送
+        let upload = match spec.body {
+            Some(Body::File(ref path)) => Some(Upload::file(path)?),
+            Some(Body::Multipart(ref parts)) => Some(Upload::multipart(parts)?),
+            _ => None,
+        };
+
+        let (mut request_len, mut request_sent_len) = (0, 0);
+        let body = match (&spec.body, upload) {
+            (Some(body), Some(upload)) => {
+                if spec.compress {
+                    return Err(anyhow!("--compress cannot be used with {}, the body is streamed from disk", body.describe()));
+                }
+                let upload = upload.progress(spec.progress);
+                if spec.header_value(header::CONTENT_TYPE.as_str()).is_none() {
+                    headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&upload.content_type)?);
+                }
+                // 大小未知时不设置 Content-Length，使用chunked传输
+                if let Some(len) = upload.len {
+                    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+                    request_len = len as usize;
+                    request_sent_len = len as usize;
+                }
+                Some(PreparedBody::Upload(upload))
+            }
+            (Some(body), None) => {
+                let bytes = body.to_bytes()?;
+                if spec.header_value(header::CONTENT_TYPE.as_str()).is_none() {
+                    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(body.content_type()));
+                }
+
+                // 开启压缩时，发送gzip后的请求体
+                request_len = bytes.len();
+                let bytes = if spec.compress {
+                    headers.append(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                    gzip(&bytes)?
+                } else {
+                    bytes
+                };
+                request_sent_len = bytes.len();
+                Some(PreparedBody::Bytes(bytes))
+            }
+            (None, _) => None,
+        };
+
+        Ok(Self {
+            method,
+            headers,
+            body,
+            request_len,
+            request_sent_len,
+        })
+    }
+
+    // 发送前签名，签名结果写进请求头
+    pub fn sign(&mut self, url: &str, signer: Option<&dyn Signer>) -> Result<()> {
+        let signer = match signer {
+            Some(signer) => signer,
+            None => return Ok(()),
+        };
+
+        let url = Url::parse(url).map_err(|e| anyhow!("invalid url {}: {}", url, e))?;
+        let body = match self.body {
+            Some(PreparedBody::Bytes(ref bytes)) => Some(bytes.as_slice()),
+            Some(PreparedBody::Upload(_)) => None,
+            None => Some(&[][..]),
+        };
+        signer.sign(&mut SignRequest {
+            method: &self.method,
+            url: &url,
+            headers: &mut self.headers,
+            body,
+        })
+    }
+
+    // 解压响应体，组装成 HttpResponse，sent 是实际发送的请求头
+    pub fn response(
+        &self,
+        sent: HeaderMap,
+        version: Version,
+        status: StatusCode,
+        headers: HeaderMap,
+        raw: &[u8],
+        duration: Duration,
+    ) -> Result<HttpResponse> {
+        let (body, decode_error) = match headers.get(header::CONTENT_ENCODING).and_then(|v| v.to_str().ok()) {
+            Some(encoding) => compress::decode_or_raw(encoding, raw),
+            None => (raw.to_vec(), None),
+        };
+
+        Ok(HttpResponse {
+            version,
+            status,
+            headers,
+            request_headers: sent,
+            body,
+            encoded_len: raw.len(),
+            decode_error,
+            request_len: self.request_len,
+            request_sent_len: self.request_sent_len,
+            duration,
+        })
+    }
+}
+
+// 发送请求，读取并解压响应
+pub async fn execute(client: &Client, spec: &RequestSpec) -> Result<HttpResponse> {
+    execute_with(client, spec, None).await
+}
+
+// 和 execute 一样，signer 会在发送前对请求签名
+pub async fn execute_with(client: &Client, spec: &RequestSpec, signer: Option<&dyn Signer>) -> Result<HttpResponse> {
+    if spec.expect_continue {
+        return Err(anyhow!("reqwest cannot wait for 100 Continue, send Expect: 100-continue requests with DirectClient"));
+    }
+    let url = Url::parse(&spec.url).map_err(|e| anyhow!("invalid url {}: {}", spec.url, e))?;
+    spec.protocol.check_url(&url)?;
+    let mut prepared = Prepared::new(spec)?;
+    prepared.sign(&spec.url, signer)?;
+    let mut req = client
+        .request(prepared.method.clone(), url)
+        .headers(prepared.headers.clone());
+    if let Some(version) = spec.protocol.request_version() {
+        req = req.version(version);
+    }
+    match prepared.body {
+        Some(PreparedBody::Bytes(ref bytes)) => req = req.body(bytes.clone()),
+        Some(PreparedBody::Upload(ref upload)) => req = req.body(reqwest::Body::wrap_stream(upload.stream()?)),
+        None => {}
+    }
+
+    let req = req.build()?;
+    let sent = req.headers().clone();
+
+    let start = Instant::now();
+    let resp = client.execute(req).await.map_err(|e| spec.protocol.explain(&spec.url, e))?;
+
+    let version = resp.version();
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let raw = resp.bytes().await?;
+
+    prepared.response(sent, version, status, headers, &raw, start.elapsed())
+}
+
+// 组合了各个发送选项的执行器：reqwest 或者 Unix domain socket、签名、--oauth2 令牌和协议版本
+// --expect-100 的请求不经过 reqwest，用 DirectClient 发送
+#[derive(Debug, Clone)]
+pub struct Executor {
+    client: Client,
+    // 获取 --oauth2 令牌用的客户端，默认和 client 相同
+    token_client: Client,
+    direct: DirectClient,
+    #[cfg(unix)]
+    unix: Option<UnixClient>,
+    signer: Option<Arc<dyn Signer>>,
+    oauth2: Option<Arc<OAuth2>>,
+    protocol: Protocol,
+    default_headers: Vec<(String, String)>,
+    progress: bool,
+}
+
+impl Executor {
+    pub fn new(client: Client) -> Self {
+        Self {
+            token_client: client.clone(),
+            client,
+            direct: DirectClient::new(),
+            #[cfg(unix)]
+            unix: None,
+            signer: None,
+            oauth2: None,
+            protocol: Protocol::Auto,
+            default_headers: Vec::new(),
+            progress: false,
+        }
+    }
+
+    // 发送 Expect: 100-continue 请求的客户端，默认请求头、超时等要和 reqwest 客户端一致
+    pub fn direct(mut self, client: DirectClient) -> Self {
+        self.direct = client;
+        self
+    }
+
+    // 通过Unix domain socket发送，不再使用 reqwest 客户端
+    #[cfg(unix)]
+    pub fn unix(mut self, client: UnixClient) -> Self {
+        self.unix = Some(client);
+        self
+    }
+
+    pub fn signer(mut self, signer: Option<Arc<dyn Signer>>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    pub fn oauth2(mut self, oauth2: Option<Arc<OAuth2>>) -> Self {
+        self.oauth2 = oauth2;
+        self
+    }
+
+    // 令牌接口一般是普通的 HTTP/1.1 服务，不能继承 --http2-prior-knowledge、--http3 等协议设置
+    pub fn token_client(mut self, client: Client) -> Self {
+        self.token_client = client;
+        self
+    }
+
+    // 要和创建 reqwest 客户端时用的协议一致
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    // 客户端的默认请求头，签名和HAR需要看到它们
+    pub fn default_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.default_headers = headers;
+        self
+    }
+
+    // 流式上传时是否显示进度条
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    // 发送时自动加上的凭据（--oauth2 令牌、--sign 签名）对应的身份，用来区分缓存
+    pub fn identity(&self, spec: &RequestSpec) -> String {
+        let oauth2 = self
+            .oauth2
+            .as_ref()
+            .filter(|_| spec.header_value("authorization").is_none())
+            .map(|oauth2| oauth2.identity());
+        let signer = self.signer.as_ref().map(|signer| signer.identity());
+        format!("{}\n{}", oauth2.unwrap_or_default(), signer.unwrap_or_default())
+    }
+
+    // 发送请求，--oauth2 时带上访问令牌，收到401时刷新令牌重试一次
+    pub async fn send(&self, spec: &RequestSpec) -> Result<HttpResponse> {
+        let mut spec = spec.clone();
+        spec.progress = self.progress;
+        spec.protocol = self.protocol;
+        spec.default_headers = self.default_headers.clone();
+
+        // 已经手动指定 Authorization 时不使用 --oauth2
+        match self.oauth2 {
+            Some(ref oauth2) if spec.header_value("authorization").is_none() => {
+                oauth2.send(&self.token_client, &spec, |spec| self.send_once(spec)).await
+            }
+            _ => self.send_once(spec).await,
+        }
+    }
+
+    async fn send_once(&self, spec: RequestSpec) -> Result<HttpResponse> {
+        #[cfg(unix)]
+        if let Some(ref unix) = self.unix {
+            return unix.execute_with(&spec, self.signer.as_deref()).await;
+        }
+        if spec.expect_continue {
+            return self.direct.execute_with(&spec, self.signer.as_deref()).await;
+        }
+        execute_with(&self.client, &spec, self.signer.as_deref()).await
+    }
+}
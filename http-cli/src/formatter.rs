@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+use anyhow::Result;
+use colored::Colorize;
+use mime::Mime;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Style, ThemeSet},
+    parsing::SyntaxSet,
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+use crate::executor::HttpResponse;
+
+// 默认的语法高亮主题
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+// 主题为 none 时不做语法高亮
+pub const NO_THEME: &str = "none";
+
+// 输出选项
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    // 打印更多细节，例如响应体压缩前后的大小
+    pub verbose: bool,
+    // 语法高亮的主题
+    pub theme: String,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            theme: DEFAULT_THEME.to_string(),
+        }
+    }
+}
+
+// 所有可用的语法高亮主题
+pub fn theme_names() -> Vec<String> {
+    ThemeSet::load_defaults().themes.into_keys().collect()
+}
+
+// 打印服务器版本 + 状态
+pub fn write_status(out: &mut dyn Write, resp: &HttpResponse) -> io::Result<()> {
+    let status = format!("{:?} {}", resp.version, resp.status).blue();
+    writeln!(out, "{}\n", status)
+}
+
+// 打印返回的响应头
+pub fn write_headers(out: &mut dyn Write, resp: &HttpResponse) -> io::Result<()> {
+    for (name, value) in resp.headers.iter() {
+        writeln!(out, "{}: {:?}", name.to_string().green(), value)?;
+    }
+    writeln!(out, "\n")
+}
+
+// verbose时打印请求体和响应体压缩前后的大小
+pub fn write_transfer(out: &mut dyn Write, resp: &HttpResponse, opts: &PrintOptions) -> io::Result<()> {
+    if !opts.verbose {
+        return Ok(());
+    }
+
+    if resp.request_sent_len != resp.request_len {
+        let info = format!("request gzip: {} bytes -> {} bytes", resp.request_len, resp.request_sent_len);
+        writeln!(out, "{}", info.yellow())?;
+    }
+
+    let info = format!(
+        "content-encoding: {}, compressed: {} bytes, decompressed: {} bytes",
+        resp.content_encoding().unwrap_or("identity"),
+        resp.encoded_len,
+        resp.body.len()
+    );
+    writeln!(out, "{}\n", info.yellow())
+}
+
+// 打印http响应体
+pub fn write_body(out: &mut dyn Write, m: Option<Mime>, body: &str, theme: &str) -> io::Result<()> {
+    // 只看类型本身，忽略 charset 之类的参数
+    match m {
+        // 对于 "application/json" 以及 application/problem+json 之类美化输出
+        Some(v) if v.essence_str() == mime::APPLICATION_JSON.as_ref() || v.suffix() == Some(mime::JSON) => {
+            write_syntect(out, body, "json", theme)
+        }
+        Some(v) if v.essence_str() == mime::TEXT_HTML.as_ref() => write_syntect(out, body, "html", theme),
+
+        // 其它 mime type，直接输出
+        _ => writeln!(out, "{}", body),
+    }
+}
+
+pub fn write_syntect(out: &mut dyn Write, s: &str, ext: &str, theme: &str) -> io::Result<()> {
+    // Load these once at the start of your program
+    let ps = SyntaxSet::load_defaults_newlines();
+    let ts = ThemeSet::load_defaults();
+    // 不高亮，或者主题不存在时原样输出
+    let theme = match ts.themes.get(theme) {
+        Some(theme) => theme,
+        None => return write!(out, "{}", s),
+    };
+    let syntax = ps
+        .find_syntax_by_extension(ext)
+        .unwrap_or_else(|| ps.find_syntax_plain_text());
+    let mut h = HighlightLines::new(syntax, theme);
+    for line in LinesWithEndings::from(s) {
+        let ranges: Vec<(Style, &str)> = h.highlight(line, &ps);
+        let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
+        write!(out, "{}", escaped)?;
+    }
+
+    // 恢复终端颜色，避免背景色带到后面的输出里
+    write!(out, "\x1b[0m")
+}
+
+// 打印整个响应
+pub fn write_resp(out: &mut dyn Write, resp: &HttpResponse, opts: &PrintOptions) -> io::Result<()> {
+    write_status(out, resp)?;
+    write_headers(out, resp)?;
+    write_transfer(out, resp, opts)?;
+    write_body(out, resp.content_type(), &resp.text(), &opts.theme)
+}
+
+// 把整个响应打印到标准输出
+pub fn print_resp(resp: &HttpResponse, opts: &PrintOptions) -> Result<()> {
+    if let Some(ref e) = resp.decode_error {
+        eprintln!("{}", format!("warning: {}, showing the raw body", e).yellow());
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write_resp(&mut out, resp, opts)?;
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use std::io::{self, Write};
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    executor::HttpResponse,
+    formatter::{write_headers, write_status, write_syntect, write_transfer, PrintOptions},
+    spec::KvPair,
+};
+
+// 标准的内省查询，和 graphql-js 的 getIntrospectionQuery 一致
+pub const INTROSPECTION_QUERY: &str = r#"query IntrospectionQuery {
+  __schema {
+    queryType { name }
+    mutationType { name }
+    subscriptionType { name }
+    types { ...FullType }
+    directives {
+      name
+      description
+      locations
+      args { ...InputValue }
+    }
+  }
+}
+
+fragment FullType on __Type {
+  kind
+  name
+  description
+  fields(includeDeprecated: true) {
+    name
+    description
+    args { ...InputValue }
+    type { ...TypeRef }
+    isDeprecated
+    deprecationReason
+  }
+  inputFields { ...InputValue }
+  interfaces { ...TypeRef }
+  enumValues(includeDeprecated: true) {
+    name
+    description
+    isDeprecated
+    deprecationReason
+  }
+  possibleTypes { ...TypeRef }
+}
+
+fragment InputValue on __InputValue {
+  name
+  description
+  type { ...TypeRef }
+  defaultValue
+}
+
+fragment TypeRef on __Type {
+  kind
+  name
+  ofType {
+    kind
+    name
+    ofType {
+      kind
+      name
+      ofType {
+        kind
+        name
+        ofType {
+          kind
+          name
+          ofType {
+            kind
+            name
+            ofType {
+              kind
+              name
+              ofType { kind name }
+            }
+          }
+        }
+      }
+    }
+  }
+}
+"#;
+
+// 构造标准的 {query, variables, operationName} 请求体
+pub fn build_body(query: &str, vars: &[KvPair], operation_name: Option<&str>) -> Value {
+    let mut variables = Map::new();
+
+    for pair in vars.iter() {
+        // 值能按json解析就按json处理（数字、布尔、对象等），否则当作字符串
+        let v = serde_json::from_str(&pair.v).unwrap_or_else(|_| Value::String(pair.v.clone()));
+        variables.insert(pair.k.clone(), v);
+    }
+
+    json!({
+        "query": query,
+        "variables": variables,
+        "operationName": operation_name,
+    })
+}
+
+// 内省查询的请求体
+pub fn introspection_body() -> Value {
+    build_body(INTROSPECTION_QUERY, &[], Some("IntrospectionQuery"))
+}
+
+// 分开打印 data 和 errors，schema为true时只打印 data.__schema
+// 返回errors的数量，调用方可以据此决定退出码
+pub fn write_graphql_resp(out: &mut dyn Write, resp: &HttpResponse, schema: bool, opts: &PrintOptions) -> Result<usize> {
+    write_status(out, resp)?;
+    write_headers(out, resp)?;
+    write_transfer(out, resp, opts)?;
+
+    let value = resp
+        .json()
+        .map_err(|e| anyhow!("response is not a valid GraphQL json: {}\n{}", e, resp.text()))?;
+
+    if schema {
+        write_data(out, value.get("data").and_then(|d| d.get("__schema")), &opts.theme)?;
+    } else {
+        write_data(out, value.get("data"), &opts.theme)?;
+    }
+
+    Ok(write_errors(out, value.get("errors"))?)
+}
+
+// 打印 data 部分
+fn write_data(out: &mut dyn Write, data: Option<&Value>, theme: &str) -> Result<()> {
+    writeln!(out, "{}", "data:".blue())?;
+    match data {
+        Some(v) if !v.is_null() => write_syntect(out, &format!("{}\n", serde_json::to_string_pretty(v)?), "json", theme)?,
+        _ => writeln!(out, "null")?,
+    }
+    writeln!(out)?;
+
+    Ok(())
+}
+
+// 打印 errors 部分，返回错误的数量
+fn write_errors(out: &mut dyn Write, errors: Option<&Value>) -> io::Result<usize> {
+    let errors = match errors.and_then(|e| e.as_array()) {
+        Some(errors) if !errors.is_empty() => errors,
+        _ => return Ok(0),
+    };
+
+    writeln!(out, "{}", "errors:".red())?;
+    for error in errors.iter() {
+        let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error");
+        writeln!(out, "  - {}", message.red())?;
+
+        if let Some(path) = error.get("path") {
+            writeln!(out, "    path: {}", path)?;
+        }
+        if let Some(locations) = error.get("locations") {
+            writeln!(out, "    locations: {}", locations)?;
+        }
+    }
+    writeln!(out)?;
+
+    Ok(errors.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::parse_kv_pair;
+
+    #[test]
+    fn build_body_works() {
+        let vars = vec![
+            parse_kv_pair("id=42").unwrap(),
+            parse_kv_pair("name=tom").unwrap(),
+        ];
+        let body = build_body("query Q { a }", &vars, Some("Q"));
+
+        assert_eq!(
+            body,
+            json!({
+                "query": "query Q { a }",
+                "variables": { "id": 42, "name": "tom" },
+                "operationName": "Q",
+            })
+        );
+
+        let body = build_body("{ a }", &[], None);
+        assert_eq!(body["variables"], json!({}));
+        assert!(body["operationName"].is_null());
+    }
+}
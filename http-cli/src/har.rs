@@ -0,0 +1,506 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    executor::HttpResponse,
+    history::redact_headers,
+    spec::{Body, RequestSpec},
+};
+
+// HTTP Archive 1.2 格式，字段定义见 http://www.softwareishard.com/blog/har-12-spec/
+// 没有用到的字段（pages、pageref、_开头的自定义字段等）保存在 extra 里，追加到其它工具写出的文件时原样写回
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Har {
+    pub log: Log,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Log {
+    pub version: String,
+    pub creator: Creator,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Creator {
+    pub name: String,
+    pub version: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub started_date_time: String,
+    // 整个请求的耗时，单位毫秒
+    pub time: f64,
+    pub request: Request,
+    pub response: Response,
+    #[serde(default)]
+    pub cache: Value,
+    #[serde(default)]
+    pub timings: Timings,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub http_version: String,
+    #[serde(default)]
+    pub cookies: Vec<Value>,
+    #[serde(default)]
+    pub headers: Vec<NameValue>,
+    #[serde(default)]
+    pub query_string: Vec<NameValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<PostData>,
+    #[serde(default = "unknown_size")]
+    pub headers_size: i64,
+    #[serde(default = "unknown_size")]
+    pub body_size: i64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    pub status: u16,
+    #[serde(default)]
+    pub status_text: String,
+    #[serde(default)]
+    pub http_version: String,
+    #[serde(default)]
+    pub cookies: Vec<Value>,
+    #[serde(default)]
+    pub headers: Vec<NameValue>,
+    pub content: Content,
+    #[serde(default, rename = "redirectURL")]
+    pub redirect_url: String,
+    #[serde(default = "unknown_size")]
+    pub headers_size: i64,
+    #[serde(default = "unknown_size")]
+    pub body_size: i64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NameValue {
+    pub name: String,
+    pub value: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostData {
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default)]
+    pub text: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    pub size: i64,
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+// HAR 要求 send、wait、receive 都不小于0，只知道总耗时，全部记在 wait 里
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timings {
+    pub send: f64,
+    pub wait: f64,
+    pub receive: f64,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn unknown_size() -> i64 {
+    -1
+}
+
+// append 写出的文件由固定的开头、每行一条的记录和固定的结尾组成
+// 追加时只需要截掉结尾再写入新记录，不用重新读写整个文件
+const FOOTER: &str = "\n]}}\n";
+
+// 除了 entries 以外的内容，包括 pages 等其它字段，写在开头
+fn header(har: &Har) -> Result<String> {
+    let mut har = serde_json::to_value(har)?;
+    let mut log = har.as_object_mut().and_then(|h| h.remove("log")).unwrap_or_default();
+    if let Some(log) = log.as_object_mut() {
+        log.remove("entries");
+    }
+    // 去掉结尾的 }，再接上 "log" 和 "entries"
+    let har = serde_json::to_string(&har)?;
+    let har = if har == "{}" { "{".to_string() } else { format!("{},", &har[..har.len() - 1]) };
+    let log = serde_json::to_string(&log)?;
+    Ok(format!("{}\"log\":{},\"entries\":[\n", har, &log[..log.len() - 1]))
+}
+
+impl Default for Har {
+    fn default() -> Self {
+        Self {
+            log: Log {
+                version: "1.2".to_string(),
+                creator: Creator {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    extra: Map::new(),
+                },
+                entries: Vec::new(),
+                extra: Map::new(),
+            },
+            extra: Map::new(),
+        }
+    }
+}
+
+impl Har {
+    // 读取HAR文件
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+
+        serde_json::from_str(&content).map_err(|e| anyhow!("invalid HAR file {}: {}", path.display(), e))
+    }
+
+    // 追加一条记录，文件不存在时新建
+    // 其它工具写出的HAR文件先整体转换一次，之后的追加都只写入新记录
+    pub fn append(path: impl AsRef<Path>, entry: Entry) -> Result<()> {
+        let path = path.as_ref();
+        let line = serde_json::to_string(&entry)?;
+
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let len = file.seek(SeekFrom::End(0))?;
+        if len >= FOOTER.len() as u64 {
+            let mut tail = vec![0; FOOTER.len()];
+            file.seek(SeekFrom::Start(len - FOOTER.len() as u64))?;
+            file.read_exact(&mut tail)?;
+            if tail == FOOTER.as_bytes() {
+                file.set_len(len - FOOTER.len() as u64)?;
+                file.seek(SeekFrom::End(0))?;
+                file.write_all(format!(",\n{}{}", line, FOOTER).as_bytes())?;
+                return Ok(());
+            }
+        }
+
+        let har = if len > 0 { Self::load(path)? } else { Self::default() };
+        let mut content = header(&har)?;
+        for old in har.log.entries.iter() {
+            content.push_str(&serde_json::to_string(old)?);
+            content.push_str(",\n");
+        }
+        content.push_str(&line);
+        content.push_str(FOOTER);
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    // 按编号取记录，编号从1开始
+    pub fn entry(&self, n: usize) -> Result<&Entry> {
+        n.checked_sub(1)
+            .and_then(|i| self.log.entries.get(i))
+            .ok_or_else(|| anyhow!("HAR entry {} not found, there are {} entries", n, self.log.entries.len()))
+    }
+}
+
+fn name_values<'a>(pairs: impl Iterator<Item = (&'a str, String)>) -> Vec<NameValue> {
+    pairs
+        .map(|(name, value)| NameValue {
+            name: name.to_string(),
+            value,
+            extra: Map::new(),
+        })
+        .collect()
+}
+
+impl Entry {
+    // 把一次请求转成HAR记录，记录实际发送的请求头，敏感请求头会被隐藏
+    pub fn new(spec: &RequestSpec, resp: &HttpResponse) -> Result<Self> {
+        let http_version = format!("{:?}", resp.version);
+        let time = resp.duration.as_secs_f64() * 1000.0;
+        let started = Utc::now() - Duration::from_std(resp.duration)?;
+
+        let query_string = Url::parse(&spec.url)
+            .map(|url| {
+                url.query_pairs()
+                    .map(|(k, v)| NameValue {
+                        name: k.into_owned(),
+                        value: v.into_owned(),
+                        extra: Map::new(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let post_data = match spec.body {
+            Some(ref body) => Some(PostData {
+                mime_type: spec
+                    .header_value("content-type")
+                    .unwrap_or_else(|| body.content_type())
+                    .to_string(),
+                // 流式上传的文件不保存内容，只记录文件名
+                text: if body.is_streamed() {
+                    body.describe()
+                } else {
+                    String::from_utf8_lossy(&body.to_bytes()?).into_owned()
+                },
+                extra: Map::new(),
+            }),
+            None => None,
+        };
+        let request_body_size = if spec.body.is_some() { resp.request_sent_len as i64 } else { 0 };
+
+        let sent: Vec<_> = resp
+            .request_headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), String::from_utf8_lossy(v.as_bytes()).into_owned()))
+            .collect();
+        let headers = redact_headers(&sent);
+        let request = Request {
+            method: spec.method.clone(),
+            url: spec.url.clone(),
+            http_version: http_version.clone(),
+            cookies: Vec::new(),
+            headers: name_values(headers.iter().map(|(k, v)| (k.as_str(), v.clone()))),
+            query_string,
+            post_data,
+            headers_size: -1,
+            body_size: request_body_size,
+            extra: Map::new(),
+        };
+
+        // 文本响应体直接保存，其它的按HAR的约定保存为base64
+        let (text, encoding) = match String::from_utf8(resp.body.clone()) {
+            Ok(text) => (text, None),
+            Err(_) => (STANDARD.encode(&resp.body), Some("base64".to_string())),
+        };
+        let response = Response {
+            status: resp.status.as_u16(),
+            status_text: resp.status.canonical_reason().unwrap_or_default().to_string(),
+            http_version,
+            cookies: Vec::new(),
+            headers: name_values(
+                resp.headers
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), String::from_utf8_lossy(v.as_bytes()).into_owned())),
+            ),
+            content: Content {
+                size: resp.body.len() as i64,
+                mime_type: resp.content_type().map(|m| m.to_string()).unwrap_or_default(),
+                text: Some(text),
+                encoding,
+                extra: Map::new(),
+            },
+            redirect_url: String::new(),
+            headers_size: -1,
+            body_size: resp.encoded_len as i64,
+            extra: Map::new(),
+        };
+
+        Ok(Self {
+            started_date_time: started.to_rfc3339(),
+            time,
+            request,
+            response,
+            cache: Value::Object(Default::default()),
+            timings: Timings {
+                send: 0.0,
+                wait: time,
+                receive: 0.0,
+                extra: Map::new(),
+            },
+            extra: Map::new(),
+        })
+    }
+
+    // 转成可以重新发送的请求
+    pub fn to_spec(&self) -> RequestSpec {
+        let mut spec = RequestSpec::new(&self.request.method, &self.request.url);
+
+        for header in self.request.headers.iter() {
+            // 跳过 HTTP/2 的伪首部，以及由客户端自己计算的首部
+            let name = header.name.to_ascii_lowercase();
+            if name.starts_with(':') || ["host", "content-length", "connection", "transfer-encoding"].contains(&name.as_str()) {
+                continue;
+            }
+            spec = spec.header(&header.name, &header.value);
+        }
+
+        if let Some(ref post_data) = self.request.post_data {
+            let json = post_data.mime_type.contains("json");
+            spec.body = match serde_json::from_str(&post_data.text) {
+                Ok(v) if json => Some(Body::Json(v)),
+                _ => Some(Body::Text(post_data.text.clone())),
+            };
+            if spec.header_value("content-type").is_none() && !post_data.mime_type.is_empty() {
+                spec = spec.header("Content-Type", &post_data.mime_type);
+            }
+        }
+
+        spec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // 浏览器导出的HAR片段，包含很多我们不关心的字段
+    const BROWSER_HAR: &str = r#"{
+      "log": {
+        "version": "1.2",
+        "creator": {"name": "WebInspector", "version": "537.36"},
+        "pages": [{"startedDateTime": "2022-06-01T08:00:00.000Z", "id": "page_1", "title": "Users", "pageTimings": {}}],
+        "entries": [
+          {
+            "pageref": "page_1",
+            "startedDateTime": "2022-06-01T08:00:00.000Z",
+            "time": 35.2,
+            "request": {
+              "method": "POST",
+              "url": "https://api.example.com/users?debug=1",
+              "httpVersion": "http/2.0",
+              "headers": [
+                {"name": ":authority", "value": "api.example.com"},
+                {"name": "content-type", "value": "application/json"},
+                {"name": "content-length", "value": "12"},
+                {"name": "x-token", "value": "abc"}
+              ],
+              "queryString": [{"name": "debug", "value": "1"}],
+              "cookies": [],
+              "headersSize": -1,
+              "bodySize": 12,
+              "postData": {"mimeType": "application/json", "text": "{\"name\":\"a\"}"}
+            },
+            "response": {
+              "status": 201,
+              "statusText": "",
+              "httpVersion": "http/2.0",
+              "headers": [],
+              "cookies": [],
+              "content": {"size": 2, "mimeType": "application/json", "text": "{}"},
+              "redirectURL": "",
+              "headersSize": -1,
+              "bodySize": -1,
+              "_transferSize": 120
+            },
+            "cache": {},
+            "timings": {"blocked": 1, "dns": -1, "send": 0.1, "wait": 30, "receive": 4},
+            "_priority": "High"
+          }
+        ]
+      }
+    }"#;
+
+    #[test]
+    fn import_works() {
+        let har: Har = serde_json::from_str(BROWSER_HAR).unwrap();
+        let spec = har.entry(1).unwrap().to_spec();
+
+        assert_eq!(spec.method, "POST");
+        assert_eq!(spec.url, "https://api.example.com/users?debug=1");
+        assert_eq!(
+            spec.headers,
+            vec![
+                ("content-type".to_string(), "application/json".to_string()),
+                ("x-token".to_string(), "abc".to_string()),
+            ]
+        );
+        assert_eq!(spec.body, Some(Body::Json(json!({"name": "a"}))));
+
+        assert!(har.entry(0).is_err());
+        assert!(har.entry(2).is_err());
+    }
+
+    #[test]
+    fn timings_works() {
+        use reqwest::{header::HeaderMap, StatusCode, Version};
+
+        let resp = HttpResponse {
+            version: Version::HTTP_11,
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            request_headers: HeaderMap::new(),
+            body: b"{}".to_vec(),
+            encoded_len: 2,
+            decode_error: None,
+            request_len: 0,
+            request_sent_len: 0,
+            duration: std::time::Duration::from_millis(25),
+        };
+        let entry = Entry::new(&RequestSpec::new("GET", "http://localhost/"), &resp).unwrap();
+        // send、wait、receive 都不能是负数，加起来等于 time
+        assert_eq!((entry.timings.send, entry.timings.wait, entry.timings.receive), (0.0, 25.0, 0.0));
+        assert_eq!(entry.time, 25.0);
+    }
+
+    #[test]
+    fn append_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.har");
+        let entry = serde_json::from_str::<Har>(BROWSER_HAR).unwrap().log.entries.remove(0);
+
+        Har::append(&path, entry.clone()).unwrap();
+        Har::append(&path, entry.clone()).unwrap();
+
+        let har = Har::load(&path).unwrap();
+        assert_eq!(har.log.version, "1.2");
+        assert_eq!(har.log.entries, vec![entry.clone(), entry.clone()]);
+        // 之后的追加只在结尾写入一行
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 4);
+
+        // 浏览器导出的文件，第一次追加时转换格式
+        let path = dir.path().join("browser.har");
+        std::fs::write(&path, BROWSER_HAR).unwrap();
+        Har::append(&path, entry.clone()).unwrap();
+        Har::append(&path, entry.clone()).unwrap();
+        let har = Har::load(&path).unwrap();
+        assert_eq!(har.log.creator.name, "WebInspector");
+        assert_eq!(har.log.entries, vec![entry.clone(), entry.clone(), entry]);
+
+        // 没有用到的字段原样保留
+        let har: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(har["log"]["pages"][0]["id"], "page_1");
+        for entry in har["log"]["entries"].as_array().unwrap() {
+            assert_eq!(entry["pageref"], "page_1");
+            assert_eq!(entry["_priority"], "High");
+            assert_eq!(entry["timings"]["blocked"], 1);
+            assert_eq!(entry["response"]["_transferSize"], 120);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{executor::HttpResponse, spec::RequestSpec};
+
+// 敏感的请求头，默认不会明文写进历史记录
+const SENSITIVE_HEADERS: [&str; 6] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-amz-security-token",
+];
+
+pub const REDACTED: &str = "<redacted>";
+
+// 一条历史记录：执行的请求 + 响应摘要，按json lines保存
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    // RFC 3339 格式的时间
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub request: RequestSpec,
+    pub status: u16,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub response_size: usize,
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+impl HistoryEntry {
+    // redact为true时，敏感请求头的值会被替换成 <redacted>
+    pub fn new(spec: &RequestSpec, resp: &HttpResponse, redact: bool) -> Self {
+        let mut request = spec.clone();
+        if redact {
+            request.headers = redact_headers(&request.headers);
+        }
+
+        Self {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            request,
+            status: resp.status.as_u16(),
+            duration_ms: resp.duration.as_millis() as u64,
+            response_size: resp.body.len(),
+            content_type: resp.content_type().map(|m| m.to_string()),
+        }
+    }
+
+    // 用于重放的请求，被隐藏的请求头无法还原，直接去掉
+    pub fn replay_spec(&self) -> RequestSpec {
+        let mut spec = self.request.clone();
+        spec.headers.retain(|(_, v)| v != REDACTED);
+        spec
+    }
+}
+
+pub fn is_sensitive(name: &str) -> bool {
+    SENSITIVE_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+pub fn redact_headers(headers: &[(String, String)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(k, v)| {
+            if is_sensitive(k) {
+                (k.clone(), REDACTED.to_string())
+            } else {
+                (k.clone(), v.clone())
+            }
+        })
+        .collect()
+}
+
+// 默认的历史记录文件，例如 ~/.local/share/http-cli/history.jsonl
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("http-cli").join("history.jsonl"))
+}
+
+// 历史记录文件
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // 追加一条记录
+    pub fn append(&self, entry: &HistoryEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+
+        Ok(())
+    }
+
+    // 读取全部记录，文件不存在时返回空列表
+    pub fn load(&self) -> Result<Vec<HistoryEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| anyhow!("invalid history entry at {}:{}: {}", self.path.display(), i + 1, e))
+            })
+            .collect()
+    }
+
+    // 按编号取记录，编号从1开始，和 history list 的输出一致
+    pub fn get(&self, n: usize) -> Result<HistoryEntry> {
+        let entries = self.load()?;
+        let total = entries.len();
+
+        n.checked_sub(1)
+            .and_then(|i| entries.into_iter().nth(i))
+            .ok_or_else(|| anyhow!("history entry {} not found, there are {} entries", n, total))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_headers_works() {
+        let headers = vec![
+            ("Authorization".to_string(), "Bearer abc".to_string()),
+            ("Accept".to_string(), "*/*".to_string()),
+        ];
+
+        assert_eq!(
+            redact_headers(&headers),
+            vec![
+                ("Authorization".to_string(), REDACTED.to_string()),
+                ("Accept".to_string(), "*/*".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn history_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path().join("nested").join("history.jsonl"));
+        assert!(history.load().unwrap().is_empty());
+
+        let entry = HistoryEntry {
+            timestamp: "2022-01-01T00:00:00+08:00".into(),
+            request: RequestSpec::get("http://localhost/a").header("Authorization", REDACTED),
+            status: 200,
+            duration_ms: 12,
+            response_size: 3,
+            content_type: Some("application/json".into()),
+        };
+        history.append(&entry).unwrap();
+        history.append(&entry).unwrap();
+
+        assert_eq!(history.load().unwrap().len(), 2);
+        assert_eq!(history.get(1).unwrap(), entry);
+        assert!(history.get(0).is_err());
+        assert!(history.get(3).is_err());
+
+        // 被隐藏的请求头不会被重放
+        assert!(history.get(2).unwrap().replay_spec().headers.is_empty());
+    }
+}
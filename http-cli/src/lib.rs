@@ -0,0 +1,31 @@
+// http-cli 的库部分：
+// spec 用可序列化的 RequestSpec 描述请求，executor 负责发送并读取响应，formatter 负责彩色输出
+// main.rs 只是基于clap的一层命令行封装，其它rust工具也可以直接复用这些模块
+
+pub mod cache;
+pub mod compress;
+pub mod config;
+pub mod diff;
+pub mod direct;
+pub mod executor;
+pub mod formatter;
+pub mod graphql;
+pub mod har;
+pub mod history;
+pub mod mock;
+pub mod oauth2;
+pub mod paginate;
+pub mod protocol;
+pub mod record;
+pub mod schema;
+pub mod session;
+pub mod sign;
+pub mod spec;
+pub mod template;
+#[cfg(unix)]
+pub mod unix;
+pub mod upload;
+
+pub use executor::{execute, execute_with, Executor, HttpResponse};
+pub use formatter::{print_resp, write_resp, PrintOptions};
+pub use spec::{parse_header, parse_kv_pair, parse_url, parse_url_with_scheme, Body, KvPair, Part, RequestItem, RequestSpec};
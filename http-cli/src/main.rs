@@ -1,218 +1,747 @@
-use std::{str::FromStr, collections::HashMap};
+use std::{fs, io::{self, IsTerminal}, net::SocketAddr, path::PathBuf, sync::Arc};
 use clap::Parser;
 use anyhow::{anyhow, Result};
-use reqwest::{header, Client, Response, Url};
-use mime::Mime;
 use colored::Colorize;
-use syntect::{
-    easy::HighlightLines,
-    highlighting::{Style, ThemeSet},
-    parsing::SyntaxSet,
-    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+
+use http_cli::{
+    cache::{self, Cache},
+    config::{self, Settings},
+    diff::{diff_snapshots, write_changes, Snapshot},
+    formatter::write_body,
+    graphql::{build_body, introspection_body, write_graphql_resp},
+    har::Har,
+    history::{self, History},
+    mock::MockServer,
+    oauth2::OAuth2,
+    paginate::{PageError, Pagination},
+    protocol::Protocol,
+    record,
+    schema::{write_violations, Schema},
+    session::Session,
+    sign::Signer,
+    parse_header, parse_kv_pair, parse_url, parse_url_with_scheme, print_resp,
+    spec::{parse_request_item, request_body},
+    template::{self, has_template, Vars},
+    Executor, HttpResponse, KvPair, PrintOptions, RequestItem, RequestSpec,
 };
 
 #[derive(Parser, Debug)]
 #[clap(version = "1.0", author="sinkhaha")]
 struct Opts {
+    // 打印更多细节，例如响应体压缩前后的大小
+    #[clap(short, long, global = true)]
+    verbose: bool,
+    // url没有写协议时默认使用https
+    #[clap(long, global = true)]
+    ssl: bool,
+    // url没有写协议时使用的默认协议，优先级高于 --ssl
+    #[clap(long, global = true, possible_values = ["http", "https"])]
+    default_scheme: Option<String>,
+    // 额外的请求头，格式为 "Name: value"，可以多次指定
+    #[clap(short = 'H', long = "header", global = true, parse(try_from_str = parse_header))]
+    headers: Vec<(String, String)>,
+    // 历史记录文件，默认为数据目录下的 http-cli/history.jsonl
+    #[clap(long, global = true)]
+    history_file: Option<String>,
+    // 历史记录里保留 Authorization 等敏感请求头的明文
+    #[clap(long, global = true)]
+    history_unredacted: bool,
+    // 把请求和响应录制到目录中，可以用 mock 子命令回放
+    #[clap(long, global = true)]
+    record: Option<String>,
+    // 把每次请求以 HTTP Archive 1.2 格式追加到文件中
+    #[clap(long, global = true)]
+    har: Option<String>,
+    // 从环境配置文件中加载变量，替换url、请求体和请求头中的 {{var}}
+    #[clap(long, global = true)]
+    env: Option<String>,
+    // 环境配置文件，默认为配置目录下的 http-cli/envs.toml
+    #[clap(long, global = true)]
+    env_file: Option<String>,
+    // 配置文件，默认为配置目录下的 http-cli/config.toml
+    #[clap(long, global = true)]
+    config: Option<String>,
+    // 忽略配置文件，只使用内置默认值、环境变量和命令行参数
+    #[clap(long, global = true, conflicts_with = "config")]
+    no_config: bool,
+    // 请求超时时间，单位秒
+    #[clap(long, global = true)]
+    timeout: Option<u64>,
+    // 语法高亮的主题，none 表示不高亮
+    #[clap(long, global = true)]
+    theme: Option<String>,
+    // 是否校验https证书，例如 --verify false
+    #[clap(long, global = true)]
+    verify: Option<bool>,
+    // 代理地址，例如 http://127.0.0.1:7890
+    #[clap(long, global = true)]
+    proxy: Option<String>,
+    // 只使用 HTTP/1.1
+    #[clap(long = "http1.1", global = true, group = "protocol")]
+    http1_1: bool,
+    // 通过TLS的ALPN协商 HTTP/2，服务端不支持时报错
+    #[clap(long, global = true, group = "protocol")]
+    http2: bool,
+    // 不协商直接使用 HTTP/2，可以用于明文的 h2c
+    #[clap(long, global = true, group = "protocol")]
+    http2_prior_knowledge: bool,
+    // 实验性的 HTTP/3（QUIC），需要开启 http3 feature 编译
+    #[clap(long, global = true, group = "protocol")]
+    http3: bool,
+    // 通过Unix domain socket发送请求，url只用来生成 Host 请求头和路径
+    #[clap(long, global = true)]
+    unix_socket: Option<String>,
+    // 缓存GET请求的响应，之后的请求自动带上 If-None-Match、If-Modified-Since
+    #[clap(long, global = true)]
+    cache: bool,
+    // 缓存目录，默认为缓存目录下的 http-cli
+    #[clap(long, global = true)]
+    cache_dir: Option<String>,
+    // 发送前用配置文件 [signers.NAME] 中的签名方式对请求签名
+    #[clap(long, global = true)]
+    sign: Option<String>,
+    // 用配置文件 [oauth2.NAME] 获取访问令牌，作为 Authorization: Bearer 发送
+    #[clap(long, global = true)]
+    oauth2: Option<String>,
+    // 合并后最终生效的配置
+    #[clap(skip)]
+    settings: Settings,
     #[clap(subcommand)]
     subcmd: SubCommand,
 }
 
-// 子命令对应两个不同的http方法
+impl Opts {
+    fn print_options(&self) -> PrintOptions {
+        PrintOptions {
+            verbose: self.verbose,
+            theme: self.settings.theme.clone(),
+        }
+    }
+
+    // 按优先级合并配置：内置默认值 < 配置文件 < 环境变量 < 命令行参数
+    fn load_settings(&self) -> Result<Settings> {
+        let mut settings = Settings::default();
+
+        if !self.no_config {
+            match self.config {
+                Some(ref path) if !std::path::Path::new(path).exists() => {
+                    return Err(anyhow!("config file {} does not exist", path));
+                }
+                Some(ref path) => settings.merge_file(path)?,
+                None => {
+                    if let Some(path) = config::default_path() {
+                        settings.merge_file(path)?;
+                    }
+                }
+            }
+        }
+
+        settings.merge_env(|name| std::env::var(name).ok())?;
+
+        if self.timeout.is_some() {
+            settings.timeout = self.timeout;
+        }
+        if let Some(ref theme) = self.theme {
+            settings.theme = theme.clone();
+        }
+        if let Some(verify) = self.verify {
+            settings.verify = verify;
+        }
+        if self.proxy.is_some() {
+            settings.proxy = self.proxy.clone();
+        }
+        settings.protocol = self.protocol();
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn protocol(&self) -> Protocol {
+        if self.http1_1 {
+            Protocol::Http1
+        } else if self.http2 {
+            Protocol::Http2
+        } else if self.http2_prior_knowledge {
+            Protocol::Http2PriorKnowledge
+        } else if self.http3 {
+            Protocol::Http3
+        } else {
+            Protocol::Auto
+        }
+    }
+
+    // 按 --sign 查找签名配置，配置中的密钥可以使用模板变量
+    fn load_signer(&self, vars: &Vars) -> Result<Option<Arc<dyn Signer>>> {
+        let name = match self.sign {
+            Some(ref name) => name,
+            None => return Ok(None),
+        };
+
+        let config = self
+            .settings
+            .signers
+            .get(name)
+            .ok_or_else(|| anyhow!("signer {} is not defined, add [signers.{}] to the config file", name, name))?;
+        Ok(Some(Arc::from(config.render(vars)?.signer())))
+    }
+
+    fn load_oauth2(&self, vars: &Vars) -> Result<Option<Arc<OAuth2>>> {
+        let name = match self.oauth2 {
+            Some(ref name) => name,
+            None => return Ok(None),
+        };
+
+        let config = self
+            .settings
+            .oauth2
+            .get(name)
+            .ok_or_else(|| anyhow!("oauth2 profile {} is not defined, add [oauth2.{}] to the config file", name, name))?;
+        let oauth2 = OAuth2::new(config.render(vars)?).map_err(|e| anyhow!("oauth2 profile {}: {}", name, e))?;
+        // 令牌和响应缓存放在同一个目录，找不到缓存目录时只缓存在内存里
+        let dir = self.cache_dir.clone().map(PathBuf::from).or_else(cache::default_dir);
+        Ok(Some(Arc::new(match dir {
+            Some(dir) => oauth2.cache_dir(name, dir),
+            None => oauth2,
+        })))
+    }
+
+    // 加载 --env 指定的环境变量，没有指定时只支持进程环境变量
+    fn vars(&self) -> Result<Vars> {
+        let env = match self.env {
+            Some(ref env) => env,
+            None => return Ok(Vars::default()),
+        };
+
+        match self.env_file {
+            Some(ref path) => Vars::load(path, env),
+            None => template::default_path()
+                .ok_or_else(|| anyhow!("cannot find the config directory, please use --env-file"))
+                .and_then(|path| Vars::load(path, env)),
+        }
+    }
+
+    fn cache(&self) -> Result<Cache> {
+        match self.cache_dir {
+            Some(ref dir) => Ok(Cache::new(dir)),
+            None => cache::default_dir()
+                .map(Cache::new)
+                .ok_or_else(|| anyhow!("cannot find the cache directory, please use --cache-dir")),
+        }
+    }
+
+    fn history(&self) -> Result<History> {
+        match self.history_file {
+            Some(ref path) => Ok(History::new(path)),
+            None => history::default_path()
+                .map(History::new)
+                .ok_or_else(|| anyhow!("cannot find the data directory, please use --history-file")),
+        }
+    }
+
+    // 按合并后的配置和 --unix-socket、--sign、--oauth2 创建发送请求的执行器
+    fn executor(&self, vars: &Vars) -> Result<Executor> {
+        let executor = Executor::new(self.settings.build_client()?)
+            .direct(self.settings.build_direct_client()?)
+            .signer(self.load_signer(vars)?)
+            .oauth2(self.load_oauth2(vars)?)
+            .token_client(self.settings.build_token_client()?)
+            .protocol(self.settings.protocol)
+            .default_headers(self.settings.headers.clone())
+            // 上传文件时，标准错误输出是终端才显示进度条
+            .progress(io::stderr().is_terminal());
+
+        match self.unix_socket {
+            Some(ref path) => with_unix_socket(executor, &self.settings, path),
+            None => Ok(executor),
+        }
+    }
+
+    fn session(&self, vars: &Vars) -> Result<Session> {
+        let mut session = Session::new(self.executor(vars)?);
+        if self.cache {
+            session.cache = Some(self.cache()?);
+        }
+        // 找不到数据目录时不保存历史记录，不影响请求本身
+        session.history = match self.history() {
+            Ok(history) => Some(history),
+            Err(e) => {
+                eprintln!("{}", format!("warning: history is not saved: {}", e).yellow());
+                None
+            }
+        };
+        session.redact = !self.history_unredacted;
+        session.record = self.record.as_ref().map(PathBuf::from);
+        session.har = self.har.as_ref().map(PathBuf::from);
+        session.verbose = self.verbose;
+        Ok(session)
+    }
+}
+
+#[cfg(unix)]
+fn with_unix_socket(executor: Executor, settings: &Settings, path: &str) -> Result<Executor> {
+    Ok(executor.unix(settings.build_unix_client(path)?))
+}
+
+#[cfg(not(unix))]
+fn with_unix_socket(_executor: Executor, _settings: &Settings, _path: &str) -> Result<Executor> {
+    Err(anyhow!("--unix-socket is only supported on unix platforms"))
+}
+
+// 子命令对应两个不同的http方法，以及graphql查询和响应对比
 #[derive(Parser, Debug)]
 enum SubCommand {
     Get(Get),
     Post(Post),
+    Graphql(Graphql),
+    Diff(Diff),
+    History(HistoryCmd),
+    Mock(Mock),
+    ImportHar(ImportHar),
+}
+
+impl SubCommand {
+    // 替换各个子命令中url和key-value对里的模板变量
+    fn render_templates(&mut self, vars: &Vars) -> Result<()> {
+        match self {
+            SubCommand::Get(args) => args.url = vars.render(&args.url)?,
+            SubCommand::Post(args) => {
+                args.url = vars.render(&args.url)?;
+                render_items(&mut args.body, vars)?;
+            }
+            SubCommand::Graphql(args) => {
+                args.url = vars.render(&args.url)?;
+                render_pairs(&mut args.vars, vars)?;
+            }
+            SubCommand::Diff(args) => {
+                args.url_a = vars.render(&args.url_a)?;
+                if let Some(url_b) = args.url_b.as_mut() {
+                    *url_b = vars.render(url_b)?;
+                }
+            }
+            SubCommand::History(_) | SubCommand::Mock(_) | SubCommand::ImportHar(_) => {}
+        }
+
+        Ok(())
+    }
+
+    // 按默认协议补全各个子命令里的url，例如 :3000/api => http://localhost:3000/api
+    fn resolve_urls(&mut self, scheme: &str) -> Result<()> {
+        match self {
+            SubCommand::Get(args) => args.url = parse_url_with_scheme(&args.url, scheme)?,
+            SubCommand::Post(args) => args.url = parse_url_with_scheme(&args.url, scheme)?,
+            SubCommand::Graphql(args) => args.url = parse_url_with_scheme(&args.url, scheme)?,
+            SubCommand::Diff(args) => {
+                args.url_a = parse_url_with_scheme(&args.url_a, scheme)?;
+                if let Some(url_b) = args.url_b.as_mut() {
+                    *url_b = parse_url_with_scheme(url_b, scheme)?;
+                }
+            }
+            SubCommand::History(_) | SubCommand::Mock(_) | SubCommand::ImportHar(_) => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn render_pairs(pairs: &mut [KvPair], vars: &Vars) -> Result<()> {
+    for pair in pairs.iter_mut() {
+        pair.k = vars.render(&pair.k)?;
+        pair.v = vars.render(&pair.v)?;
+    }
+
+    Ok(())
+}
+
+fn render_items(items: &mut [RequestItem], vars: &Vars) -> Result<()> {
+    for item in items.iter_mut() {
+        match item {
+            RequestItem::Field(pair) => render_pairs(std::slice::from_mut(pair), vars)?,
+            RequestItem::File { name, path, .. } => {
+                *name = vars.render(name)?;
+                *path = vars.render(path)?;
+            }
+            RequestItem::Body(path) => *path = vars.render(path)?,
+        }
+    }
+
+    Ok(())
+}
+
+// 校验url，带模板变量的url要等替换之后才能校验
+fn validate_url(s: &str) -> Result<String> {
+    if has_template(s) {
+        return Ok(s.to_string());
+    }
+    parse_url(s)
 }
 
 // get子命令
 #[derive(Parser, Debug)]
 struct Get {
-    // 使用parse_url函数解析url
-    #[clap(parse(try_from_str=parse_url))]
+    // 使用validate_url函数校验url，模板变量和协议在main中替换、补全
+    #[clap(validator = validate_url)]
     url: String, // 请求的url
+    // 自动翻页，把每一页的数组合并后输出
+    #[clap(long)]
+    paginate: bool,
+    // 游标字段的路径，例如 next_cursor、meta.next，不指定时跟随 Link: rel="next" 响应头
+    #[clap(long, requires = "paginate")]
+    cursor: Option<String>,
+    // 游标不是url时，用来传递游标的查询参数
+    #[clap(long, default_value = "cursor", requires = "cursor")]
+    cursor_param: String,
+    // 每一页中要合并的数组的路径，例如 data.items，不指定时整个响应体必须是数组
+    #[clap(long, requires = "paginate")]
+    items: Option<String>,
+    // 最多请求的页数
+    #[clap(long, default_value = "100", requires = "paginate")]
+    max_pages: usize,
+    // 用 JSON Schema 文件校验响应体，不符合时退出码非0
+    #[clap(long, conflicts_with = "paginate")]
+    schema: Option<String>,
 }
 
 // post子命令
 #[derive(Parser, Debug)]
 struct Post {
-    #[clap(parse(try_from_str = parse_url))]
+    #[clap(validator = validate_url)]
+    url: String,
+    // 请求体，k=v 为字符串，k:=v 按json解析，field@path 上传文件，@path 把整个文件作为请求体
+    #[clap(parse(try_from_str = parse_request_item))]
+    body: Vec<RequestItem>,
+    // 以 multipart/form-data 发送，有 field@path 时自动开启
+    #[clap(long)]
+    multipart: bool,
+    // 推断 k=v 中值的类型，例如 age=30 发送数字，active=true 发送布尔
+    #[clap(long)]
+    infer_types: bool,
+    // 用 JSON Schema 文件校验响应体，不符合时退出码非0
+    #[clap(long)]
+    schema: Option<String>,
+    // 用gzip压缩请求体，并设置 Content-Encoding: gzip
+    #[clap(long)]
+    compress: bool,
+    // 发送 Expect: 100-continue，让服务端在上传前就可以拒绝请求
+    #[clap(long)]
+    expect_100: bool,
+}
+
+// graphql子命令
+#[derive(Parser, Debug)]
+struct Graphql {
+    #[clap(validator = validate_url)]
     url: String,
-    #[clap(parse(try_from_str = parse_kv_pair))]
-    body: Vec<KvPair> // 请求体
+    // 存放查询语句的文件，例如 query.graphql
+    #[clap(short, long, required_unless_present = "schema")]
+    query: Option<String>,
+    // 查询变量，格式为 k=v，可以多次指定
+    #[clap(long = "var", parse(try_from_str = parse_kv_pair))]
+    vars: Vec<KvPair>,
+    // 文档中有多个操作时，指定要执行的操作名
+    #[clap(long)]
+    operation_name: Option<String>,
+    // 发送内省查询，打印服务端的schema
+    #[clap(long, conflicts_with = "query")]
+    schema: bool,
 }
 
-// 解析url的方法
-fn parse_url(s: &str) -> Result<String> {
-    let _url: Url = s.parse()?; // 检查下url是否合法
-    Ok(s.into())
+// diff子命令，对比两个响应，或者对比一个响应和保存的文件
+#[derive(Parser, Debug)]
+struct Diff {
+    #[clap(validator = validate_url)]
+    url_a: String,
+    #[clap(validator = validate_url, required_unless_present = "against-file")]
+    url_b: Option<String>,
+    // 和保存的响应体文件对比，此时只比较响应体
+    #[clap(long, conflicts_with = "url-b")]
+    against_file: Option<String>,
+    // 忽略的路径，例如 headers.date、body.meta.timestamp、body.items[*].updated_at，可以多次指定
+    #[clap(long)]
+    ignore: Vec<String>,
 }
 
-// key-value对
-#[derive(Debug, PartialEq)]
-struct KvPair {
-    k: String,
-    v: String,
+// history子命令，查看和重放执行过的请求
+#[derive(Parser, Debug)]
+struct HistoryCmd {
+    #[clap(subcommand)]
+    action: HistoryAction,
 }
 
-// KvPair实现FromStr
-impl FromStr for KvPair {
-    type Err = anyhow::Error;
+#[derive(Parser, Debug)]
+enum HistoryAction {
+    // 列出所有记录
+    List,
+    // 查看第N条记录的详情
+    Show { n: usize },
+    // 重新发送第N条记录的请求
+    Replay { n: usize },
+}
 
-    // 实现from_str方法
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // 根据=分割，得到一个迭代器
-        let mut split = s.split("=");
-        let err = || anyhow!(format!("failed to parse {}", s));
+// mock子命令，用录制的响应启动一个本地服务
+#[derive(Parser, Debug)]
+struct Mock {
+    // 录制文件所在的目录，即 --record 指定的目录
+    #[clap(long)]
+    from: String,
+    #[clap(short, long, default_value = "8080")]
+    port: u16,
+    #[clap(long, default_value = "127.0.0.1")]
+    host: String,
+    // 除了方法、路径和查询参数，还要求请求体一致
+    #[clap(long)]
+    match_body: bool,
+}
 
-        Ok(Self {
-            k: (split.next().ok_or_else(err)?).to_string(), // 从迭代器取第一个结果为key,迭代器返回 Some(T)/None,将其转换成 Ok(T)/Err(E)，然后用 ? 处理错误
-            v: (split.next().ok_or_else(err)?).to_string(), // 从迭代器取第二个结果为value
-        })
-    }
+// import-har子命令，重放浏览器导出的HAR文件中的一条请求
+#[derive(Parser, Debug)]
+struct ImportHar {
+    file: String,
+    // 要重放的记录编号，从1开始；不指定时列出所有记录
+    #[clap(long)]
+    entry: Option<usize>,
 }
 
-// 因为KvPair 实现了 FromStr，这里可以直接 s.parse() 得到 KvPair
-fn parse_kv_pair(s: &str) -> Result<KvPair>{
-    Ok(s.parse()?)
+// 给请求加上命令行里的 -H 请求头
+fn with_headers(mut spec: RequestSpec, headers: &[(String, String)]) -> RequestSpec {
+    for (name, value) in headers.iter() {
+        spec = spec.header(name, value);
+    }
+    spec
 }
 
-// get命令的处理方法
-async fn get_handle(client: Client, args: &Get) -> Result<()>{
-    let resp = client.get(&args.url).send().await?;
+// 打印完响应后用schema校验响应体，有不符合的地方时返回错误，退出码非0
+fn check_schema(schema: Option<&Schema>, resp: &HttpResponse) -> Result<()> {
+    let schema = match schema {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
 
-    Ok(print_resp(resp).await?)
+    let violations = schema.validate_body(&resp.body);
+    write_violations(&mut io::stdout().lock(), &violations)?;
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("response does not match the schema"))
+    }
 }
 
-// post命令的处理方法
-async fn post_handle(client: Client, args: &Post)-> Result<()> {
-    let mut body = HashMap::new();
-
-    for pair in args.body.iter() {
-        body.insert(&pair.k, &pair.v);
+// get命令的处理方法
+async fn get_handle(session: &Session, args: &Get, opts: &Opts) -> Result<()> {
+    if args.paginate {
+        return paginate_handle(session, args, opts).await;
     }
 
-    let resp = client.post(&args.url).json(&body).send().await?;
+    // 先加载schema，schema有问题时不发送请求
+    let schema = args.schema.as_ref().map(Schema::load).transpose()?;
+    let spec = with_headers(RequestSpec::get(&args.url), &opts.headers);
+    let resp = session.send(&spec).await?;
 
-    Ok(print_resp(resp).await?)
+    print_resp(&resp, &opts.print_options())?;
+    check_schema(schema.as_ref(), &resp)
 }
 
-//===========================一些工具函数=====================================
+// get --paginate 的处理方法，逐页请求并合并数组，某一页失败时打印这一页的响应
+async fn paginate_handle(session: &Session, args: &Get, opts: &Opts) -> Result<()> {
+    let pagination = Pagination {
+        cursor: args.cursor.clone(),
+        cursor_param: args.cursor_param.clone(),
+        items: args.items.clone(),
+        max_pages: args.max_pages,
+    };
+
+    let spec = with_headers(RequestSpec::get(&args.url), &opts.headers);
+    let items = match pagination.fetch_all(session, &spec).await {
+        Ok(items) => items,
+        Err(e) => {
+            if let Some(page) = e.downcast_ref::<PageError>() {
+                print_resp(&page.resp, &opts.print_options())?;
+            }
+            return Err(e);
+        }
+    };
+
+    let body = format!("{}\n", serde_json::to_string_pretty(&items)?);
+    write_body(&mut io::stdout().lock(), Some(mime::APPLICATION_JSON), &body, &opts.settings.theme)?;
 
-// 打印服务器版本 + 状态
-fn print_status(resp: &Response) {
-    let status = format!("{:?} {}", resp.version(), resp.status()).blue();
-    println!("{}\n", status);
+    Ok(())
 }
 
-// 打印返回的响应头
-fn print_headers(resp: &Response) {
-    for (name, value) in resp.headers() {
-        println!("{}: {:?}", name.to_string().green(), value);
-    }
-    println!("\n");
+// post命令的处理方法
+async fn post_handle(session: &Session, args: &Post, opts: &Opts) -> Result<()> {
+    let schema = args.schema.as_ref().map(Schema::load).transpose()?;
+    let spec = RequestSpec::post(&args.url)
+        .body(request_body(&args.body, args.multipart, args.infer_types)?)
+        .compress(args.compress)
+        .expect_continue(args.expect_100);
+    let resp = session.send(&with_headers(spec, &opts.headers)).await?;
+
+    print_resp(&resp, &opts.print_options())?;
+    check_schema(schema.as_ref(), &resp)
 }
 
-// 打印http响应体
-fn print_body(m: Option<Mime>, body: &String) {
-    match m {
-        // 对于 "application/json"美化输出
-        Some(v) if v == mime::APPLICATION_JSON => print_syntect(body, "json"),
-        Some(v) if v == mime::TEXT_HTML => print_syntect(body, "html"),
+// graphql命令的处理方法，errors非空时返回Err，让进程以非0退出
+async fn graphql_handle(session: &Session, args: &Graphql, opts: &Opts) -> Result<()> {
+    let body = match args.query {
+        Some(ref path) if !args.schema => {
+            let query = fs::read_to_string(path)
+                .map_err(|e| anyhow!("failed to read query file {}: {}", path, e))?;
+            build_body(&query, &args.vars, args.operation_name.as_deref())
+        }
+        _ => introspection_body(),
+    };
+
+    let spec = with_headers(RequestSpec::post(&args.url).json(body), &opts.headers);
+    let resp = session.send(&spec).await?;
 
-        // 其它 mime type，直接输出
-        _ => println!("{}", body),
+    let errors = write_graphql_resp(&mut io::stdout().lock(), &resp, args.schema, &opts.print_options())?;
+    if errors > 0 {
+        return Err(anyhow!("GraphQL response contains {} error(s)", errors));
     }
-}
 
-// 将服务器返回的 content-type 解析成 Mime 类型
-fn get_content_type(resp: &Response) -> Option<Mime> {
-    resp.headers()
-        .get(header::CONTENT_TYPE)
-        .map(|v| v.to_str().unwrap().parse().unwrap())   
+    Ok(())
 }
 
-// 打印整个响应
-async fn print_resp(resp: Response) -> Result<()>{
-    print_status(&resp);
-    print_headers(&resp);
+// diff命令的处理方法，有差异时返回Err，让进程以非0退出
+async fn diff_handle(session: &Session, args: &Diff, opts: &Opts) -> Result<()> {
+    let fetch = |url: &str| {
+        let spec = with_headers(RequestSpec::get(url), &opts.headers);
+        async move { session.send(&spec).await.map(|resp| Snapshot::from_response(&resp)) }
+    };
 
-    let mime = get_content_type(&resp);
-    let body = resp.text().await?;
-    print_body(mime, &body);
+    let (a, b) = match (&args.url_b, &args.against_file) {
+        (Some(url_b), _) => tokio::try_join!(fetch(&args.url_a), fetch(url_b))?,
+        (None, Some(file)) => {
+            let saved = fs::read_to_string(file)
+                .map_err(|e| anyhow!("failed to read {}: {}", file, e))?;
+            (fetch(&args.url_a).await?, Snapshot::from_body(&saved))
+        }
+        (None, None) => return Err(anyhow!("either URL_B or --against-file is required")),
+    };
 
-    Ok(())
+    let changes = diff_snapshots(&a, &b, &args.ignore);
+    if changes.is_empty() {
+        println!("{}", "no differences".green());
+        return Ok(());
+    }
+
+    write_changes(&mut io::stdout().lock(), &changes)?;
+    println!();
+
+    Err(anyhow!("found {} difference(s)", changes.len()))
 }
 
-fn print_syntect(s: &str, ext: &str) {
-    // Load these once at the start of your program
-    let ps = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-    let syntax = ps.find_syntax_by_extension(ext).unwrap();
-    let mut h = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
-    for line in LinesWithEndings::from(s) {
-        let ranges: Vec<(Style, &str)> = h.highlight(line, &ps);
-        let escaped = as_24_bit_terminal_escaped(&ranges[..], true);
-        print!("{}", escaped);
+// history命令的处理方法，只有 replay 需要发送请求，list 和 show 不创建会话
+async fn history_handle(session: impl FnOnce() -> Result<Session>, args: &HistoryCmd, opts: &Opts) -> Result<()> {
+    let history = opts.history()?;
+
+    match args.action {
+        HistoryAction::List => {
+            for (i, entry) in history.load()?.iter().enumerate() {
+                println!(
+                    "{:>4}  {}  {} {} {} {}",
+                    i + 1,
+                    entry.timestamp,
+                    entry.request.method.blue(),
+                    entry.request.url,
+                    entry.status.to_string().green(),
+                    format!("{}ms", entry.duration_ms).yellow()
+                );
+            }
+            Ok(())
+        }
+        HistoryAction::Show { n } => {
+            println!("{}", serde_json::to_string_pretty(&history.get(n)?)?);
+            Ok(())
+        }
+        HistoryAction::Replay { n } => {
+            let entry = history.get(n)?;
+            let spec = with_headers(entry.replay_spec(), &opts.headers);
+            // 脱敏的请求头不会重放，提示没有通过 -H 重新传入的那些
+            for (name, value) in entry.request.headers.iter() {
+                if value == history::REDACTED && spec.header_value(name).is_none() {
+                    let msg = format!("warning: header {} was redacted and is not replayed, pass it again with -H", name);
+                    eprintln!("{}", msg.yellow());
+                }
+            }
+
+            let resp = session()?.send(&spec).await?;
+            print_resp(&resp, &opts.print_options())
+        }
     }
 }
 
-//================================================================
-
-// 在http请求时使用异步
-#[tokio::main]
-async fn main() -> Result<()>{
-    let opts: Opts = Opts::parse(); // parse函数是#[derive(Clap)]自动生成的
+// mock命令的处理方法
+async fn mock_handle(args: &Mock) -> Result<()> {
+    let exchanges = record::load_dir(&args.from)?;
+    println!("loaded {} recorded exchange(s) from {}", exchanges.len(), args.from);
 
-    let mut headers = header::HeaderMap::new();
-    // 添加一些默认的请求头
-    headers.insert("X-POWERED-BY", "Rust".parse()?);
-    headers.insert(header::USER_AGENT, "Rust Httpie".parse()?);
+    let addr: SocketAddr = format!("{}:{}", args.host, args.port)
+        .parse()
+        .map_err(|e| anyhow!("invalid listen address {}:{}: {}", args.host, args.port, e))?;
 
-    // http请求客户端
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()?;
+    MockServer::new(exchanges).match_body(args.match_body).run(addr).await
+}
 
-    // 匹配命令，不同命令执行不同的处理方法
-    let result = match opts.subcmd {
-        SubCommand::Get(ref args) => get_handle(client, args).await?,
-        SubCommand::Post(ref args) => post_handle(client, args).await?,
+// import-har命令的处理方法
+async fn import_har_handle(session: &Session, args: &ImportHar, opts: &Opts) -> Result<()> {
+    let har = Har::load(&args.file)?;
+
+    let n = match args.entry {
+        Some(n) => n,
+        None => {
+            for (i, entry) in har.log.entries.iter().enumerate() {
+                println!(
+                    "{:>4}  {} {} {}",
+                    i + 1,
+                    entry.request.method.blue(),
+                    entry.request.url,
+                    entry.response.status.to_string().green()
+                );
+            }
+            return Ok(());
+        }
     };
 
-    Ok(result)
-}
+    let spec = with_headers(har.entry(n)?.to_spec(), &opts.headers);
+    let resp = session.send(&spec).await?;
 
-// ========================单元测试========================================
-// 仅在运行 cargo test 时才编译
-#[cfg(test)]
-mod tests {
-    use super::*;
+    print_resp(&resp, &opts.print_options())
+}
 
-    #[test]
-    fn parse_url_works() {
-        assert!(parse_url("abc").is_err());
-        assert!(parse_url("http://abc.xyz").is_ok());
-        assert!(parse_url("https://httpbin.org/post").is_ok());
+// 在http请求时使用异步
+#[tokio::main]
+async fn main() -> Result<()>{
+    let mut opts: Opts = Opts::parse(); // parse函数是#[derive(Clap)]自动生成的
+
+    // 先替换模板变量，再补全url
+    // 没有指定 --env 时 {{var}} 只查进程环境变量，找不到时报错，避免把模板原样发出去
+    let vars = opts.vars()?;
+    for (name, value) in opts.headers.iter_mut() {
+        *name = vars.render(name)?;
+        *value = vars.render(value)?;
     }
+    opts.subcmd.render_templates(&vars)?;
 
-    #[test]
-    fn parse_kv_pair_works() {
-        assert!(parse_kv_pair("a").is_err());
-        assert_eq!(
-            parse_kv_pair("a=1").unwrap(),
-            KvPair {
-                k: "a".into(),
-                v: "1".into()
-            }
-        );
+    // 补全url的协议：--default-scheme 优先，其次 --ssl，默认http
+    let scheme = match opts.default_scheme {
+        Some(ref scheme) => scheme.clone(),
+        None if opts.ssl => "https".to_string(),
+        None => "http".to_string(),
+    };
+    opts.subcmd.resolve_urls(&scheme)?;
 
-        assert_eq!(
-            parse_kv_pair("b=").unwrap(),
-            KvPair {
-                k: "b".into(),
-                v: "".into()
-            }
-        );
+    // 发送请求的会话，默认请求头、超时、证书校验和代理都来自合并后的配置，mock 不需要
+    opts.settings = opts.load_settings()?;
+    let session = || opts.session(&vars);
+
+    // 匹配命令，不同命令执行不同的处理方法
+    match opts.subcmd {
+        SubCommand::Get(ref args) => get_handle(&session()?, args, &opts).await,
+        SubCommand::Post(ref args) => post_handle(&session()?, args, &opts).await,
+        SubCommand::Graphql(ref args) => graphql_handle(&session()?, args, &opts).await,
+        SubCommand::Diff(ref args) => diff_handle(&session()?, args, &opts).await,
+        SubCommand::History(ref args) => history_handle(session, args, &opts).await,
+        SubCommand::Mock(ref args) => mock_handle(args).await,
+        SubCommand::ImportHar(ref args) => import_har_handle(&session()?, args, &opts).await,
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,214 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use anyhow::Result;
+use colored::Colorize;
+use hyper::{
+    header::{HeaderName, HeaderValue, CONTENT_ENCODING},
+    service::{make_service_fn, service_fn},
+    Body as HyperBody, Request, Response, Server, StatusCode,
+};
+use reqwest::Url;
+use serde_json::{json, Value};
+
+use crate::{
+    compress,
+    record::Exchange,
+    spec::Body,
+};
+
+// 用录制的请求/响应模拟服务端
+// 按方法、路径、查询参数（忽略顺序）匹配，可选地再匹配请求体
+#[derive(Debug, Clone)]
+pub struct MockServer {
+    exchanges: Vec<Exchange>,
+    match_body: bool,
+}
+
+// 拆出路径和排好序的查询参数
+fn target(url: &Url) -> (String, Vec<(String, String)>) {
+    let mut query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    query.sort();
+
+    (url.path().to_string(), query)
+}
+
+// 请求体是否和录制的一致，json按语义对比
+fn body_matches(expected: &Option<Body>, actual: &[u8]) -> bool {
+    match expected {
+        None => actual.is_empty(),
+        Some(Body::Json(v)) => serde_json::from_slice::<Value>(actual)
+            .map(|a| &a == v)
+            .unwrap_or(false),
+        Some(Body::Text(s)) => s.as_bytes() == actual,
+        // 录制时没有保存上传的文件内容，无法比较
+        Some(Body::File(_)) | Some(Body::Multipart(_)) => false,
+    }
+}
+
+impl MockServer {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        Self {
+            exchanges,
+            match_body: false,
+        }
+    }
+
+    pub fn match_body(mut self, match_body: bool) -> Self {
+        self.match_body = match_body;
+        self
+    }
+
+    // 查找第一个匹配的录制，path_and_query 形如 /api/users?page=2
+    pub fn find(&self, method: &str, path_and_query: &str, body: &[u8]) -> Option<&Exchange> {
+        let actual = Url::parse("http://mock")
+            .and_then(|base| base.join(path_and_query))
+            .ok()
+            .map(|url| target(&url))?;
+
+        self.exchanges.iter().find(|e| {
+            e.request.method.eq_ignore_ascii_case(method)
+                && Url::parse(&e.request.url).map(|url| target(&url) == actual).unwrap_or(false)
+                && (!self.match_body || body_matches(&e.request.body, body))
+        })
+    }
+
+    async fn handle(self: Arc<Self>, req: Request<HyperBody>) -> Result<Response<HyperBody>> {
+        let method = req.method().to_string();
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "/".to_string());
+        let encoding = req
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        let raw = hyper::body::to_bytes(req.into_body()).await?;
+        let body = match encoding {
+            Some(ref encoding) => compress::decode(encoding, &raw)?,
+            None => raw.to_vec(),
+        };
+
+        let resp = match self.find(&method, &path_and_query, &body) {
+            Some(exchange) => {
+                let recorded = &exchange.response;
+                let mut resp = Response::new(HyperBody::from(recorded.body_bytes()?));
+                *resp.status_mut() = StatusCode::from_u16(recorded.status)?;
+                for (name, value) in recorded.headers.iter() {
+                    if let (Ok(name), Ok(value)) = (
+                        HeaderName::from_bytes(name.as_bytes()),
+                        HeaderValue::from_str(value),
+                    ) {
+                        resp.headers_mut().append(name, value);
+                    }
+                }
+                resp
+            }
+            None => {
+                let error = json!({ "error": format!("no recorded response matches {} {}", method, path_and_query) });
+                let mut resp = Response::new(HyperBody::from(error.to_string()));
+                *resp.status_mut() = StatusCode::NOT_FOUND;
+                resp.headers_mut().insert(hyper::header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                resp
+            }
+        };
+
+        let status = resp.status().to_string();
+        let status = if resp.status().is_success() { status.green() } else { status.red() };
+        println!("{} {} -> {}", method.blue(), path_and_query, status);
+
+        Ok(resp)
+    }
+
+    // 在后台启动服务，返回实际监听的地址（端口为0时会随机分配）
+    pub async fn spawn(self, addr: SocketAddr) -> Result<SocketAddr> {
+        let server = Arc::new(self);
+        let make_svc = make_service_fn(move |_conn| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move {
+                        // 处理出错时返回500，而不是断开连接
+                        Ok::<_, Infallible>(server.handle(req).await.unwrap_or_else(|e| {
+                            let mut resp = Response::new(HyperBody::from(e.to_string()));
+                            *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                            resp
+                        }))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&addr)?.serve(make_svc);
+        let local_addr = server.local_addr();
+        tokio::spawn(server);
+
+        Ok(local_addr)
+    }
+
+    // 启动服务并一直运行
+    pub async fn run(self, addr: SocketAddr) -> Result<()> {
+        let addr = self.spawn(addr).await?;
+        println!("mock server listening on {}", format!("http://{}", addr).green());
+
+        std::future::pending::<()>().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        record::RecordedResponse,
+        spec::RequestSpec,
+    };
+
+    fn exchange(spec: RequestSpec, body: &str) -> Exchange {
+        Exchange {
+            request: spec,
+            response: RecordedResponse {
+                status: 200,
+                headers: vec![("content-type".into(), "application/json".into())],
+                body: body.into(),
+                body_base64: None,
+            },
+        }
+    }
+
+    #[test]
+    fn find_works() {
+        let server = MockServer::new(vec![
+            exchange(RequestSpec::get("http://api.example.com/users?page=2&size=10"), "[2]"),
+            exchange(RequestSpec::post("http://api.example.com/users").json(json!({"name": "a"})), "{}"),
+            exchange(RequestSpec::post("http://api.example.com/users").json(json!({"name": "b"})), "{\"b\": 1}"),
+        ]);
+
+        // 查询参数的顺序不影响匹配
+        assert_eq!(server.find("GET", "/users?size=10&page=2", b"").unwrap().response.body, "[2]");
+        assert!(server.find("GET", "/users?page=3&size=10", b"").is_none());
+        assert!(server.find("DELETE", "/users", b"").is_none());
+
+        // 默认不匹配请求体，取第一个
+        assert_eq!(server.find("POST", "/users", b"{\"name\": \"b\"}").unwrap().response.body, "{}");
+
+        let server = server.match_body(true);
+        assert_eq!(server.find("POST", "/users", b"{\"name\": \"b\"}").unwrap().response.body, "{\"b\": 1}");
+        assert!(server.find("POST", "/users", b"{\"name\": \"c\"}").is_none());
+    }
+
+    #[tokio::test]
+    async fn serve_works() {
+        let server = MockServer::new(vec![exchange(RequestSpec::get("http://a.b/ping"), "{\"pong\": true}")]);
+        let addr = server.spawn(([127, 0, 0, 1], 0).into()).await.unwrap();
+
+        let resp = reqwest::get(format!("http://{}/ping", addr)).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), "{\"pong\": true}");
+
+        let resp = reqwest::get(format!("http://{}/missing", addr)).await.unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+}
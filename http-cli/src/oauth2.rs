@@ -0,0 +1,523 @@
+use std::{
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use colored::Colorize;
+use reqwest::{header::ACCEPT, Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{executor::HttpResponse, sign::sha256_hex, spec::RequestSpec, template::Vars};
+
+// 令牌在过期前这么多秒就当作已经过期，避免发送途中失效
+const EXPIRY_MARGIN: i64 = 30;
+// 设备码流程中服务端没有返回 interval 时的轮询间隔，单位秒
+const DEFAULT_INTERVAL: u64 = 5;
+
+// 配置文件中的 OAuth2 配置，例如：
+// [oauth2.api]
+// grant = "client-credentials"
+// token_url = "https://auth.example.com/oauth/token"
+// client_id = "cli"
+// client_secret = "${API_CLIENT_SECRET}"
+// scope = "read write"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OAuth2Config {
+    #[serde(default)]
+    pub grant: Grant,
+    pub token_url: String,
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+    // refresh-token 方式使用的初始刷新令牌，之后服务端返回的新刷新令牌保存在缓存里
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    // device-code 方式获取设备码的地址
+    #[serde(default)]
+    pub device_authorization_url: Option<String>,
+    // 客户端认证方式：basic 用 Authorization: Basic，body 把 client_secret 放在表单里
+    #[serde(default)]
+    pub client_auth: ClientAuth,
+}
+
+// 获取令牌的方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Grant {
+    #[default]
+    ClientCredentials,
+    RefreshToken,
+    DeviceCode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientAuth {
+    #[default]
+    Basic,
+    Body,
+}
+
+impl OAuth2Config {
+    pub fn render(&self, vars: &Vars) -> Result<Self> {
+        let render = |s: &Option<String>| s.as_deref().map(|s| vars.render(s)).transpose();
+        Ok(Self {
+            token_url: vars.render(&self.token_url)?,
+            client_id: vars.render(&self.client_id)?,
+            client_secret: render(&self.client_secret)?,
+            refresh_token: render(&self.refresh_token)?,
+            device_authorization_url: render(&self.device_authorization_url)?,
+            ..self.clone()
+        })
+    }
+
+    // 检查所选方式需要的字段
+    pub fn validate(&self) -> Result<()> {
+        match self.grant {
+            Grant::RefreshToken if self.refresh_token.is_none() => Err(anyhow!("refresh-token grant requires refresh_token")),
+            Grant::DeviceCode if self.device_authorization_url.is_none() => {
+                Err(anyhow!("device-code grant requires device_authorization_url"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// 获取到的访问令牌
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Token {
+    pub access_token: String,
+    pub token_type: String,
+    // 过期时间，unix秒，服务端没有返回 expires_in 时为None，一直用到收到401为止
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+impl Token {
+    pub fn is_valid(&self, now: i64) -> bool {
+        self.expires_at.map(|t| now < t - EXPIRY_MARGIN).unwrap_or(true)
+    }
+
+    // Authorization 请求头的值，token_type 统一写成 Bearer
+    pub fn authorization(&self) -> String {
+        let token_type = if self.token_type.eq_ignore_ascii_case("bearer") {
+            "Bearer"
+        } else {
+            self.token_type.as_str()
+        };
+        format!("{} {}", token_type, self.access_token)
+    }
+}
+
+// 令牌接口的成功响应
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    token_type: Option<String>,
+    #[serde(default)]
+    expires_in: Option<u64>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+// 令牌接口的错误响应，例如 {"error": "authorization_pending"}
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+impl ErrorResponse {
+    fn into_error(self, url: &str) -> anyhow::Error {
+        match self.error_description {
+            Some(desc) => anyhow!("token request to {} failed: {} ({})", url, self.error, desc),
+            None => anyhow!("token request to {} failed: {}", url, self.error),
+        }
+    }
+}
+
+// 设备码接口的响应
+#[derive(Debug, Deserialize)]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    #[serde(alias = "verification_url")]
+    verification_uri: String,
+    #[serde(default)]
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    #[serde(default)]
+    interval: Option<u64>,
+}
+
+// 按配置获取并缓存令牌，令牌同时保存在内存和缓存目录里，过期前重复使用
+#[derive(Debug)]
+pub struct OAuth2 {
+    config: OAuth2Config,
+    // 令牌缓存文件，None 时只缓存在内存里
+    cache: Option<PathBuf>,
+    token: Mutex<Option<Token>>,
+}
+
+impl OAuth2 {
+    pub fn new(config: OAuth2Config) -> Result<Self> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            cache: None,
+            token: Mutex::new(None),
+        })
+    }
+
+    // 令牌缓存在 dir/oauth2 下，文件名由配置的名字、令牌地址、client_id和scope决定
+    pub fn cache_dir(mut self, name: &str, dir: impl AsRef<Path>) -> Self {
+        let key = format!(
+            "{}\n{}\n{}\n{}",
+            name,
+            self.config.token_url,
+            self.config.client_id,
+            self.config.scope.as_deref().unwrap_or_default()
+        );
+        self.cache = Some(dir.as_ref().join("oauth2").join(format!("{}.json", sha256_hex(key.as_bytes()))));
+        self
+    }
+
+    // 令牌对应的身份，不同配置的响应分开缓存，只用来计算哈希
+    pub fn identity(&self) -> String {
+        format!("{:?}", self.config)
+    }
+
+    // 带上访问令牌发送请求，令牌可能已经被吊销，收到401时重新获取后重试一次
+    pub async fn send<F, Fut>(&self, client: &Client, spec: &RequestSpec, send: F) -> Result<HttpResponse>
+    where
+        F: Fn(RequestSpec) -> Fut,
+        Fut: Future<Output = Result<HttpResponse>>,
+    {
+        let token = self.token(client, false).await?;
+        let resp = send(spec.clone().header("Authorization", &token.authorization())).await?;
+        if resp.status != StatusCode::UNAUTHORIZED {
+            return Ok(resp);
+        }
+
+        eprintln!("{}", "oauth2: got 401, refreshing the token and retrying".yellow());
+        let token = self.token(client, true).await?;
+        send(spec.clone().header("Authorization", &token.authorization())).await
+    }
+
+    // 返回可用的令牌，force 为true时（例如收到401后）忽略缓存重新获取
+    pub async fn token(&self, client: &Client, force: bool) -> Result<Token> {
+        let cached = self.cached();
+        if let Some(ref token) = cached {
+            if !force && token.is_valid(Utc::now().timestamp()) {
+                return Ok(token.clone());
+            }
+        }
+
+        // 优先用刷新令牌，刷新失败时回到配置的方式重新获取
+        let refresh_token = cached
+            .and_then(|t| t.refresh_token)
+            .or_else(|| self.config.refresh_token.clone());
+        let token = match refresh_token {
+            Some(ref refresh_token) => match self.refresh(client, refresh_token).await {
+                Ok(token) => token,
+                Err(e) if self.config.grant == Grant::RefreshToken => return Err(e),
+                Err(_) => self.grant(client).await?,
+            },
+            None => self.grant(client).await?,
+        };
+
+        self.save(&token);
+        Ok(token)
+    }
+
+    fn cached(&self) -> Option<Token> {
+        if let Some(token) = self.token.lock().unwrap().clone() {
+            return Some(token);
+        }
+        let content = fs::read_to_string(self.cache.as_ref()?).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    // 令牌缓存写入失败不影响请求，只打印警告
+    fn save(&self, token: &Token) {
+        *self.token.lock().unwrap() = Some(token.clone());
+        if let Some(ref path) = self.cache {
+            if let Err(e) = write_private(path, &serde_json::to_vec_pretty(token).unwrap_or_default()) {
+                eprintln!("{}", format!("warning: failed to cache oauth2 token: {}", e).yellow());
+            }
+        }
+    }
+
+    async fn grant(&self, client: &Client) -> Result<Token> {
+        match self.config.grant {
+            Grant::ClientCredentials => {
+                let mut params = vec![("grant_type", "client_credentials".to_string())];
+                if let Some(ref scope) = self.config.scope {
+                    params.push(("scope", scope.clone()));
+                }
+                self.request(client, params).await?.map_err(|e| e.into_error(&self.config.token_url))
+            }
+            // validate 已经保证有 refresh_token
+            Grant::RefreshToken => self.refresh(client, self.config.refresh_token.as_deref().unwrap_or_default()).await,
+            Grant::DeviceCode => self.device_code(client).await,
+        }
+    }
+
+    async fn refresh(&self, client: &Client, refresh_token: &str) -> Result<Token> {
+        let params = vec![
+            ("grant_type", "refresh_token".to_string()),
+            ("refresh_token", refresh_token.to_string()),
+        ];
+        let mut token = self.request(client, params).await?.map_err(|e| e.into_error(&self.config.token_url))?;
+        // 服务端没有返回新的刷新令牌时继续用原来的
+        if token.refresh_token.is_none() {
+            token.refresh_token = Some(refresh_token.to_string());
+        }
+        Ok(token)
+    }
+
+    // RFC 8628 设备码流程：提示用户在浏览器中授权，然后轮询令牌接口
+    async fn device_code(&self, client: &Client) -> Result<Token> {
+        let url = self.config.device_authorization_url.as_deref().unwrap_or_default();
+        let mut params = vec![("client_id", self.config.client_id.clone())];
+        if let Some(ref scope) = self.config.scope {
+            params.push(("scope", scope.clone()));
+        }
+        let resp = client.post(url).header(ACCEPT, "application/json").form(&params).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("device authorization request to {} failed: {}", url, resp.status()));
+        }
+        let auth: DeviceAuthorization = resp
+            .json()
+            .await
+            .map_err(|e| anyhow!("invalid device authorization response from {}: {}", url, e))?;
+
+        match auth.verification_uri_complete {
+            Some(ref uri) => eprintln!("{} {}", "oauth2: to authorize, open".yellow(), uri),
+            None => eprintln!(
+                "{} {} {} {}",
+                "oauth2: to authorize, open".yellow(),
+                auth.verification_uri,
+                "and enter the code".yellow(),
+                auth.user_code.bold()
+            ),
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(auth.expires_in);
+        let mut interval = Duration::from_secs(auth.interval.unwrap_or(DEFAULT_INTERVAL));
+        loop {
+            tokio::time::sleep(interval).await;
+            if Instant::now() >= deadline {
+                return Err(anyhow!("device code expired before authorization completed"));
+            }
+
+            let params = vec![
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code".to_string()),
+                ("device_code", auth.device_code.clone()),
+            ];
+            match self.request(client, params).await? {
+                Ok(token) => return Ok(token),
+                Err(e) if e.error == "authorization_pending" => {}
+                Err(e) if e.error == "slow_down" => interval += Duration::from_secs(DEFAULT_INTERVAL),
+                Err(e) => return Err(e.into_error(&self.config.token_url)),
+            }
+        }
+    }
+
+    // 请求令牌接口，OAuth2 的错误响应单独返回，方便设备码流程判断是否继续轮询
+    async fn request(&self, client: &Client, mut params: Vec<(&str, String)>) -> Result<Result<Token, ErrorResponse>> {
+        let config = &self.config;
+        let mut req = client.post(&config.token_url).header(ACCEPT, "application/json");
+        match (config.client_auth, config.client_secret.as_ref()) {
+            (ClientAuth::Basic, Some(secret)) => req = req.basic_auth(&config.client_id, Some(secret)),
+            (_, secret) => {
+                params.push(("client_id", config.client_id.clone()));
+                if let Some(secret) = secret {
+                    params.push(("client_secret", secret.clone()));
+                }
+            }
+        }
+
+        let resp = req.form(&params).send().await?;
+        let status = resp.status();
+        let body = resp.bytes().await?;
+        if status.is_success() {
+            let token: TokenResponse = serde_json::from_slice(&body)
+                .map_err(|e| anyhow!("invalid token response from {}: {}", config.token_url, e))?;
+            return Ok(Ok(Token {
+                access_token: token.access_token,
+                token_type: token.token_type.unwrap_or_else(|| "Bearer".to_string()),
+                expires_at: token.expires_in.map(|s| Utc::now().timestamp() + s as i64),
+                refresh_token: token.refresh_token,
+            }));
+        }
+
+        serde_json::from_slice::<ErrorResponse>(&body)
+            .map(Err)
+            .map_err(|_| anyhow!("token request to {} failed: {}", config.token_url, status))
+    }
+}
+
+// 令牌文件只允许当前用户读写
+fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    std::io::Write::write_all(&mut options.open(path)?, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body as HyperBody, Request, Response, Server, StatusCode,
+    };
+    use serde_json::{json, Value};
+
+    // 模拟的令牌服务，记录收到的令牌请求数
+    async fn token_server(calls: Arc<AtomicUsize>) -> SocketAddr {
+        let make = make_service_fn(move |_| {
+            let calls = calls.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<HyperBody>| {
+                    let calls = calls.clone();
+                    async move {
+                        let path = req.uri().path().to_string();
+                        let auth = req.headers().get("authorization").map(|v| v.to_str().unwrap().to_string());
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let form: HashMap<String, String> = String::from_utf8_lossy(&body)
+                            .split('&')
+                            .filter_map(|kv| kv.split_once('='))
+                            .map(|(k, v)| (k.to_string(), v.to_string()))
+                            .collect();
+
+                        let (status, value) = respond(&path, auth, &form, calls.fetch_add(1, Ordering::SeqCst));
+                        let mut resp = Response::new(HyperBody::from(value.to_string()));
+                        *resp.status_mut() = status;
+                        Ok::<_, Infallible>(resp)
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    fn respond(path: &str, auth: Option<String>, form: &HashMap<String, String>, call: usize) -> (StatusCode, Value) {
+        let get = |k: &str| form.get(k).map(|s| s.as_str());
+        match (path, get("grant_type")) {
+            ("/device", _) => (
+                StatusCode::OK,
+                json!({"device_code": "dc", "user_code": "ABCD", "verification_uri": "https://auth/device", "expires_in": 60, "interval": 0}),
+            ),
+            // "Y2xpOnM=" 是 cli:s
+            (_, Some("client_credentials")) if auth.as_deref() == Some("Basic Y2xpOnM=") && get("scope") == Some("read") => (
+                StatusCode::OK,
+                json!({"access_token": format!("t{}", call), "token_type": "bearer", "expires_in": 3600, "refresh_token": "r1"}),
+            ),
+            (_, Some("refresh_token")) if get("refresh_token") == Some("r1") => {
+                (StatusCode::OK, json!({"access_token": "refreshed", "expires_in": 3600}))
+            }
+            (_, Some(grant)) if grant.ends_with("device_code") && get("client_id") == Some("cli") => match call {
+                1 => (StatusCode::BAD_REQUEST, json!({"error": "authorization_pending"})),
+                _ => (StatusCode::OK, json!({"access_token": "device", "token_type": "Bearer"})),
+            },
+            _ => (StatusCode::BAD_REQUEST, json!({"error": "invalid_grant", "error_description": "bad request"})),
+        }
+    }
+
+    fn config(addr: SocketAddr, grant: Grant) -> OAuth2Config {
+        OAuth2Config {
+            grant,
+            token_url: format!("http://{}/token", addr),
+            client_id: "cli".into(),
+            client_secret: Some("s".into()),
+            scope: Some("read".into()),
+            refresh_token: None,
+            device_authorization_url: Some(format!("http://{}/device", addr)),
+            client_auth: ClientAuth::Basic,
+        }
+    }
+
+    #[tokio::test]
+    async fn client_credentials_works() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let addr = token_server(calls.clone()).await;
+        let dir = tempfile::tempdir().unwrap();
+        let client = Client::new();
+
+        let oauth2 = OAuth2::new(config(addr, Grant::ClientCredentials)).unwrap().cache_dir("api", dir.path());
+        let token = oauth2.token(&client, false).await.unwrap();
+        assert_eq!(token.authorization(), "Bearer t0");
+        assert!(token.is_valid(Utc::now().timestamp()));
+
+        // 令牌缓存在文件里，新进程不会重新请求
+        let oauth2 = OAuth2::new(config(addr, Grant::ClientCredentials)).unwrap().cache_dir("api", dir.path());
+        assert_eq!(oauth2.token(&client, false).await.unwrap().access_token, "t0");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // 强制刷新时使用刷新令牌，并保留原来的刷新令牌
+        let token = oauth2.token(&client, true).await.unwrap();
+        assert_eq!(token.access_token, "refreshed");
+        assert_eq!(token.refresh_token.as_deref(), Some("r1"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // 密钥错误时返回服务端的错误信息
+        let mut bad = config(addr, Grant::ClientCredentials);
+        bad.client_secret = Some("wrong".into());
+        let err = OAuth2::new(bad).unwrap().token(&client, false).await.unwrap_err();
+        assert!(err.to_string().contains("invalid_grant (bad request)"));
+
+        assert!(OAuth2::new(config(addr, Grant::RefreshToken)).is_err());
+        assert!(!Token { expires_at: Some(100), ..token }.is_valid(80));
+    }
+
+    #[tokio::test]
+    async fn device_code_works() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let addr = token_server(calls.clone()).await;
+
+        // 第一次轮询返回 authorization_pending，第二次拿到令牌
+        let mut config = config(addr, Grant::DeviceCode);
+        config.client_auth = ClientAuth::Body;
+        let token = OAuth2::new(config).unwrap().token(&Client::new(), false).await.unwrap();
+        assert_eq!(token, Token {
+            access_token: "device".into(),
+            token_type: "Bearer".into(),
+            expires_at: None,
+            refresh_token: None,
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
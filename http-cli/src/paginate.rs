@@ -0,0 +1,257 @@
+use std::{collections::HashSet, fmt};
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use reqwest::{header::{HeaderMap, LINK}, Url};
+use serde_json::Value;
+
+use crate::{diff::segments, executor::HttpResponse, session::Session, spec::RequestSpec};
+
+// 自动翻页的规则
+// 默认跟随 RFC 5988 的 Link: <...>; rel="next" 响应头，指定 cursor 时改为读取响应体里的游标字段
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    // 游标字段的路径，例如 next_cursor、meta.next
+    pub cursor: Option<String>,
+    // 游标不是url时，用这个查询参数带到下一页的请求里
+    pub cursor_param: String,
+    // 每一页中要合并的数组的路径，例如 data.items，不指定时整个响应体必须是数组
+    pub items: Option<String>,
+    pub max_pages: usize,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            cursor_param: "cursor".to_string(),
+            items: None,
+            max_pages: 100,
+        }
+    }
+}
+
+// 某一页的状态码不是2xx，带上这一页的响应，调用方可以把它打印出来
+#[derive(Debug)]
+pub struct PageError {
+    pub page: usize,
+    pub url: String,
+    pub resp: HttpResponse,
+}
+
+impl fmt::Display for PageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "page {} ({}) failed with status {}", self.page, self.url, self.resp.status)
+    }
+}
+
+impl std::error::Error for PageError {}
+
+// 按路径取json中的值，例如 data.items、pages[0].next
+pub fn value_at<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    segments(path).into_iter().try_fold(value, |v, key| match v {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+// 解析 Link 响应头，返回 (url, rel列表)
+pub fn parse_link(s: &str) -> Vec<(String, Vec<String>)> {
+    let mut links = Vec::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let url = rest[start + 1..end].trim().to_string();
+
+        // 参数部分到下一个链接为止
+        rest = &rest[end + 1..];
+        let params = &rest[..rest.find('<').unwrap_or(rest.len())];
+        let rels = params
+            .split(';')
+            .filter_map(|p| p.trim().split_once('='))
+            .filter(|(k, _)| k.trim().eq_ignore_ascii_case("rel"))
+            .flat_map(|(_, v)| {
+                v.trim()
+                    .trim_end_matches(',')
+                    .trim_matches('"')
+                    .split_whitespace()
+                    .map(|r| r.to_ascii_lowercase())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+
+        links.push((url, rels));
+    }
+
+    links
+}
+
+// 从 Link 响应头中找出下一页的地址，相对地址按当前url补全
+pub fn next_link(headers: &HeaderMap, base: &str) -> Option<String> {
+    let base = Url::parse(base).ok()?;
+
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(parse_link)
+        .find(|(_, rels)| rels.iter().any(|r| r == "next"))
+        .and_then(|(url, _)| base.join(&url).ok())
+        .map(|url| url.to_string())
+}
+
+impl Pagination {
+    // 从 spec 的url开始逐页请求，合并每一页的数组
+    // 没有下一页、下一页已经请求过或者达到 max_pages 时结束
+    pub async fn fetch_all(&self, session: &Session, spec: &RequestSpec) -> Result<Vec<Value>> {
+        let mut spec = spec.clone();
+        let mut visited = HashSet::new();
+        let mut items = Vec::new();
+        for page in 1..=self.max_pages {
+            visited.insert(spec.url.clone());
+            let resp = session.send(&spec).await?;
+            if !resp.status.is_success() {
+                return Err(PageError { page, url: spec.url, resp }.into());
+            }
+
+            let body = resp.json().map_err(|e| anyhow!("page {} ({}): {}", page, spec.url, e))?;
+            let page_items = self.items(&body)?;
+            if session.verbose {
+                eprintln!("{}", format!("page {}: {} -> {} items", page, spec.url, page_items.len()).yellow());
+            }
+            items.extend(page_items);
+
+            match self.next_url(&spec.url, &resp.headers, &body)? {
+                Some(next) if !visited.contains(&next) => spec.url = next,
+                _ => break,
+            }
+            if page == self.max_pages {
+                eprintln!("{}", format!("warning: stopped after --max-pages {}, more pages are available", page).yellow());
+            }
+        }
+
+        Ok(items)
+    }
+
+    // 下一页的地址，没有下一页时返回None
+    pub fn next_url(&self, url: &str, headers: &HeaderMap, body: &Value) -> Result<Option<String>> {
+        let field = match self.cursor {
+            Some(ref field) => field,
+            None => return Ok(next_link(headers, url)),
+        };
+
+        let cursor = match value_at(body, field) {
+            None | Some(Value::Null) => return Ok(None),
+            Some(Value::String(s)) if s.is_empty() => return Ok(None),
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Number(n)) => n.to_string(),
+            Some(other) => return Err(anyhow!("cursor field {} should be a string or number, got {}", field, other)),
+        };
+
+        let mut url = Url::parse(url).map_err(|e| anyhow!("invalid url {}: {}", url, e))?;
+        // 游标本身就是地址时直接跳转，否则替换查询参数
+        if cursor.starts_with('/') || cursor.starts_with("http://") || cursor.starts_with("https://") {
+            return Ok(Some(url.join(&cursor)?.to_string()));
+        }
+
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .into_owned()
+            .filter(|(k, _)| k != &self.cursor_param)
+            .collect();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair(&self.cursor_param, &cursor);
+
+        Ok(Some(url.to_string()))
+    }
+
+    // 取出一页中要合并的数组
+    pub fn items(&self, body: &Value) -> Result<Vec<Value>> {
+        let items = match self.items {
+            Some(ref path) => value_at(body, path).ok_or_else(|| anyhow!("items path {} not found in response", path))?,
+            None => body,
+        };
+
+        match items {
+            Value::Array(items) => Ok(items.clone()),
+            _ => Err(anyhow!(
+                "{} is not an array, use --items to choose the array to collect",
+                self.items.as_deref().unwrap_or("response body")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    #[test]
+    fn next_link_works() {
+        let links = parse_link(r#"<https://api.example.com/users?page=2>; rel="next", <https://api.example.com/users?page=5>; rel="last""#);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0], ("https://api.example.com/users?page=2".to_string(), vec!["next".to_string()]));
+        assert_eq!(links[1].1, vec!["last".to_string()]);
+
+        let mut headers = HeaderMap::new();
+        headers.append(LINK, HeaderValue::from_static(r#"</users?page=1>; rel="prev first""#));
+        headers.append(LINK, HeaderValue::from_static(r#"</users?page=3>; rel=next"#));
+        assert_eq!(
+            next_link(&headers, "http://localhost:3000/users?page=2"),
+            Some("http://localhost:3000/users?page=3".to_string())
+        );
+
+        assert_eq!(next_link(&HeaderMap::new(), "http://localhost:3000/users"), None);
+    }
+
+    #[test]
+    fn cursor_works() {
+        let pagination = Pagination {
+            cursor: Some("meta.next".to_string()),
+            items: Some("data".to_string()),
+            ..Default::default()
+        };
+        let headers = HeaderMap::new();
+        let url = "http://localhost:3000/users?size=10&cursor=a";
+
+        let body = json!({"data": [1, 2], "meta": {"next": "b"}});
+        assert_eq!(
+            pagination.next_url(url, &headers, &body).unwrap(),
+            Some("http://localhost:3000/users?size=10&cursor=b".to_string())
+        );
+        assert_eq!(pagination.items(&body).unwrap(), vec![json!(1), json!(2)]);
+
+        // 游标是地址时直接跳转
+        let body = json!({"data": [], "meta": {"next": "/users?after=9"}});
+        assert_eq!(
+            pagination.next_url(url, &headers, &body).unwrap(),
+            Some("http://localhost:3000/users?after=9".to_string())
+        );
+
+        // 游标为空或者不存在时结束
+        assert_eq!(pagination.next_url(url, &headers, &json!({"meta": {"next": null}})).unwrap(), None);
+        assert_eq!(pagination.next_url(url, &headers, &json!({"meta": {"next": ""}})).unwrap(), None);
+        assert_eq!(pagination.next_url(url, &headers, &json!({})).unwrap(), None);
+
+        assert!(pagination.items(&json!({"data": {}})).is_err());
+        assert!(Pagination::default().items(&json!({"data": []})).is_err());
+        assert_eq!(Pagination::default().items(&json!([1])).unwrap(), vec![json!(1)]);
+    }
+
+    #[test]
+    fn value_at_works() {
+        let value = json!({"data": {"pages": [{"next": "x"}]}});
+        assert_eq!(value_at(&value, "data.pages[0].next"), Some(&json!("x")));
+        assert_eq!(value_at(&value, "data.pages.0.next"), Some(&json!("x")));
+        assert_eq!(value_at(&value, "data.missing"), None);
+        assert_eq!(value_at(&value, ""), Some(&value));
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use anyhow::{anyhow, Result};
+use reqwest::{ClientBuilder, Url, Version};
+
+// 命令行选择的http协议版本
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    // https 时通过ALPN协商，优先 h2，http 时使用 HTTP/1.1
+    #[default]
+    Auto,
+    // 只使用 HTTP/1.1
+    Http1,
+    // 通过ALPN协商 h2，服务端不支持时在发送请求前报错，只能用于 https
+    Http2,
+    // 不协商，直接发送 HTTP/2 请求，可以用于明文的 h2c
+    Http2PriorKnowledge,
+    // 实验性的 HTTP/3（QUIC），需要开启 http3 feature 编译
+    Http3,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Protocol::Auto => "auto",
+            Protocol::Http1 => "--http1.1",
+            Protocol::Http2 => "--http2",
+            Protocol::Http2PriorKnowledge => "--http2-prior-knowledge",
+            Protocol::Http3 => "--http3",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Protocol {
+    // 按协议配置 reqwest 客户端，协议在建立连接时就确定，不会先用其它版本发出请求
+    // --http2 也用 http2_prior_knowledge：https 时ALPN只提供 h2，协商不出 h2 时连接失败
+    pub fn apply(self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        Ok(match self {
+            Protocol::Auto => builder,
+            Protocol::Http1 => builder.http1_only(),
+            Protocol::Http2 | Protocol::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+            #[cfg(feature = "http3")]
+            Protocol::Http3 => builder.http3_prior_knowledge(),
+            #[cfg(not(feature = "http3"))]
+            Protocol::Http3 => {
+                return Err(anyhow!(
+                    "--http3 is experimental and not compiled in, rebuild with: RUSTFLAGS=\"--cfg reqwest_unstable\" cargo build --features http3"
+                ))
+            }
+        })
+    }
+
+    // reqwest 只有请求的版本是 HTTP/3 时才会走QUIC
+    pub fn request_version(self) -> Option<Version> {
+        match self {
+            Protocol::Http3 => Some(Version::HTTP_3),
+            _ => None,
+        }
+    }
+
+    // 发送前检查url，h2 只能通过TLS的ALPN协商，HTTP/3 也必须是 https
+    pub fn check_url(self, url: &Url) -> Result<()> {
+        match self {
+            Protocol::Http2 if url.scheme() != "https" => Err(anyhow!(
+                "--http2 negotiates h2 via TLS ALPN and needs https, use --http2-prior-knowledge for cleartext h2c: {}",
+                url
+            )),
+            Protocol::Http3 if url.scheme() != "https" => Err(anyhow!("--http3 needs an https url: {}", url)),
+            _ => Ok(()),
+        }
+    }
+
+    // 连接或者协商失败时补充说明，原始错误放在后面
+    pub fn explain(self, url: &str, e: reqwest::Error) -> anyhow::Error {
+        match self {
+            Protocol::Http2PriorKnowledge => anyhow!(
+                "HTTP/2 with prior knowledge to {} failed, the server may not speak h2c: {}",
+                url,
+                e
+            ),
+            Protocol::Http3 => anyhow!("HTTP/3 request to {} failed, QUIC over UDP may be blocked or unsupported: {}", url, e),
+            Protocol::Http2 => anyhow!("HTTP/2 negotiation with {} failed, the server may not support h2: {}", url, e),
+            _ => e.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_works() {
+        let http = Url::parse("http://localhost:3000/api").unwrap();
+        let https = Url::parse("https://example.com/api").unwrap();
+
+        assert!(Protocol::Http2.check_url(&http).is_err());
+        assert!(Protocol::Http2.check_url(&https).is_ok());
+        assert!(Protocol::Http2PriorKnowledge.check_url(&http).is_ok());
+        assert!(Protocol::Http3.check_url(&http).is_err());
+
+        assert_eq!(Protocol::Http3.request_version(), Some(Version::HTTP_3));
+        assert_eq!(Protocol::Http2.request_version(), None);
+        #[cfg(not(feature = "http3"))]
+        assert!(Protocol::Http3.apply(reqwest::Client::builder()).is_err());
+    }
+}
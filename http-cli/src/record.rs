@@ -0,0 +1,202 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{header, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{executor::HttpResponse, history::redact_headers, spec::RequestSpec};
+
+// 录制下来的响应，响应体是解压后的内容
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    // utf-8 的响应体直接保存文本，其它的保存为base64
+    #[serde(default)]
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_base64: Option<String>,
+}
+
+impl RecordedResponse {
+    pub fn from_response(resp: &HttpResponse) -> Self {
+        // 响应体已经解压过了，和编码、长度相关的响应头不再适用
+        let headers = resp
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                *name != header::CONTENT_ENCODING
+                    && *name != header::CONTENT_LENGTH
+                    && *name != header::TRANSFER_ENCODING
+            })
+            .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect();
+
+        let (body, body_base64) = match String::from_utf8(resp.body.clone()) {
+            Ok(text) => (text, None),
+            Err(_) => (String::new(), Some(STANDARD.encode(&resp.body))),
+        };
+
+        Self {
+            status: resp.status.as_u16(),
+            headers,
+            body,
+            body_base64,
+        }
+    }
+
+    pub fn body_bytes(&self) -> Result<Vec<u8>> {
+        match self.body_base64 {
+            Some(ref b64) => Ok(STANDARD.decode(b64)?),
+            None => Ok(self.body.as_bytes().to_vec()),
+        }
+    }
+}
+
+// 一次请求和对应的响应
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub request: RequestSpec,
+    pub response: RecordedResponse,
+}
+
+impl Exchange {
+    // 录制时会隐藏敏感请求头，匹配时用不到它们
+    pub fn new(spec: &RequestSpec, resp: &HttpResponse) -> Self {
+        let mut request = spec.clone();
+        request.headers = redact_headers(&request.headers);
+
+        Self {
+            request,
+            response: RecordedResponse::from_response(resp),
+        }
+    }
+}
+
+// 把路径转成适合做文件名的形式：/api/users?id=1 => api-users
+fn slug(path: &str) -> String {
+    let slug = path
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        "root".to_string()
+    } else {
+        slug
+    }
+}
+
+// 保存到目录中，文件名形如 0001-GET-api-users.json，返回保存的路径
+pub fn save(dir: impl AsRef<Path>, exchange: &Exchange) -> Result<PathBuf> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    let path = Url::parse(&exchange.request.url)
+        .map(|u| u.path().to_string())
+        .unwrap_or_default();
+    let content = serde_json::to_string_pretty(exchange)?;
+
+    // 删除过文件时数量会小于最大序号，所以接着最大的序号编号；
+    // 多个进程同时录制时用 create_new 避免覆盖，文件已存在就换下一个序号
+    let mut seq = list_files(dir)?.iter().filter_map(|f| seq_of(f)).max().unwrap_or(0) + 1;
+    loop {
+        let file = dir.join(format!("{:04}-{}-{}.json", seq, exchange.request.method, slug(&path)));
+        match OpenOptions::new().write(true).create_new(true).open(&file) {
+            Ok(mut f) => {
+                f.write_all(content.as_bytes())?;
+                return Ok(file);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => seq += 1,
+            Err(e) => return Err(anyhow!("failed to write {}: {}", file.display(), e)),
+        }
+    }
+}
+
+// 文件名开头的序号，例如 0012-GET-api.json 返回 12
+fn seq_of(file: &Path) -> Option<usize> {
+    let name = file.file_name()?.to_str()?;
+    name.split('-').next()?.parse().ok()
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+// 按文件名顺序读取目录中所有录制的请求
+pub fn load_dir(dir: impl AsRef<Path>) -> Result<Vec<Exchange>> {
+    let dir = dir.as_ref();
+
+    list_files(dir)
+        .map_err(|e| anyhow!("failed to read {}: {}", dir.display(), e))?
+        .iter()
+        .map(|file| {
+            let content = fs::read_to_string(file)?;
+            serde_json::from_str(&content).map_err(|e| anyhow!("invalid recording {}: {}", file.display(), e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slug_works() {
+        assert_eq!(slug("/api/users"), "api-users");
+        assert_eq!(slug("/api/users/1/"), "api-users-1");
+        assert_eq!(slug("/"), "root");
+    }
+
+    #[test]
+    fn save_and_load_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let exchange = Exchange {
+            request: RequestSpec::get("http://localhost/api/users?page=2"),
+            response: RecordedResponse {
+                status: 200,
+                headers: vec![("content-type".into(), "application/json".into())],
+                body: "[]".into(),
+                body_base64: None,
+            },
+        };
+
+        let first = save(dir.path(), &exchange).unwrap();
+        let second = save(dir.path(), &exchange).unwrap();
+        assert!(first.ends_with("0001-GET-api-users.json"));
+        assert!(second.ends_with("0002-GET-api-users.json"));
+
+        assert_eq!(load_dir(dir.path()).unwrap(), vec![exchange.clone(), exchange.clone()]);
+
+        // 删除前面的文件后，不会覆盖已有的最大序号
+        fs::remove_file(&first).unwrap();
+        let third = save(dir.path(), &exchange).unwrap();
+        assert!(third.ends_with("0003-GET-api-users.json"));
+        assert!(second.exists());
+        assert_eq!(seq_of(Path::new("0012-GET-api.json")), Some(12));
+        assert_eq!(seq_of(Path::new("notes.json")), None);
+    }
+
+    #[test]
+    fn body_bytes_works() {
+        let resp = RecordedResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: String::new(),
+            body_base64: Some(STANDARD.encode([0xff, 0x00])),
+        };
+        assert_eq!(resp.body_bytes().unwrap(), vec![0xff, 0x00]);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{fs, io::{self, Write}, path::Path};
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use jsonschema::{Draft, JSONSchema};
+use serde_json::Value;
+
+// 编译好的 JSON Schema，没有写 $schema 时按 draft 2020-12 处理
+pub struct Schema {
+    compiled: JSONSchema,
+}
+
+// 一处不符合schema的地方
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    // 出错的位置，JSON Pointer 格式，例如 /items/0/id
+    pub instance_path: String,
+    pub message: String,
+}
+
+impl Schema {
+    pub fn new(schema: &Value) -> Result<Self> {
+        let mut options = JSONSchema::options();
+        if schema.get("$schema").is_none() {
+            options.with_draft(Draft::Draft202012);
+        }
+
+        let compiled = options
+            .compile(schema)
+            .map_err(|e| anyhow!("invalid JSON Schema at {}: {}", pointer(&e.schema_path.to_string()), e))?;
+        Ok(Self { compiled })
+    }
+
+    // 从文件读取schema
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+        let schema: Value = serde_json::from_str(&content).map_err(|e| anyhow!("invalid json in {}: {}", path.display(), e))?;
+
+        Self::new(&schema).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    // 校验json，返回所有不符合的地方
+    pub fn validate(&self, instance: &Value) -> Vec<Violation> {
+        match self.compiled.validate(instance) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .map(|e| Violation {
+                    instance_path: pointer(&e.instance_path.to_string()),
+                    message: e.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    // 校验响应体，响应体不是json时也算一处错误
+    pub fn validate_body(&self, body: &[u8]) -> Vec<Violation> {
+        match serde_json::from_slice(body) {
+            Ok(instance) => self.validate(&instance),
+            Err(e) => vec![Violation {
+                instance_path: pointer(""),
+                message: format!("response is not a valid json: {}", e),
+            }],
+        }
+    }
+}
+
+// 根路径显示为 /
+fn pointer(path: &str) -> String {
+    if path.is_empty() {
+        "/".to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+// 打印所有不符合schema的地方
+pub fn write_violations(out: &mut dyn Write, violations: &[Violation]) -> io::Result<()> {
+    // 和响应体之间空一行，响应体末尾不一定有换行
+    writeln!(out)?;
+    if violations.is_empty() {
+        return writeln!(out, "{}", "schema: ok".green());
+    }
+
+    writeln!(out, "{}", format!("schema: {} violations", violations.len()).red())?;
+    for v in violations.iter() {
+        writeln!(out, "  {} {}", v.instance_path.yellow(), v.message)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validate_works() {
+        let schema = Schema::new(&json!({
+            "type": "object",
+            "required": ["items"],
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["id"],
+                        "properties": {"id": {"type": "integer"}}
+                    }
+                },
+                // 2020-12 才有的关键字
+                "tuple": {"prefixItems": [{"type": "string"}]}
+            }
+        }))
+        .unwrap();
+
+        assert!(schema.validate(&json!({"items": [{"id": 1}], "tuple": ["a"]})).is_empty());
+
+        let violations = schema.validate(&json!({"items": [{"id": 1}, {"id": "2"}, {}], "tuple": [1]}));
+        let paths: Vec<&str> = violations.iter().map(|v| v.instance_path.as_str()).collect();
+        assert_eq!(paths.len(), 3);
+        assert!(paths.contains(&"/items/1/id"));
+        assert!(paths.contains(&"/items/2"));
+        assert!(paths.contains(&"/tuple/0"));
+
+        assert_eq!(schema.validate(&json!([])).len(), 1);
+        assert_eq!(schema.validate_body(b"not json")[0].instance_path, "/");
+    }
+
+    #[test]
+    fn load_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("schema.json");
+
+        fs::write(&path, r#"{"type": "array"}"#).unwrap();
+        assert!(Schema::load(&path).unwrap().validate_body(b"[1, 2]").is_empty());
+
+        fs::write(&path, r#"{"type": "no-such-type"}"#).unwrap();
+        assert!(Schema::load(&path).is_err());
+
+        fs::write(&path, "{").unwrap();
+        assert!(Schema::load(&path).is_err());
+        assert!(Schema::load(dir.path().join("missing.json")).is_err());
+    }
+}
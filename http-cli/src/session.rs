@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{
+    cache::{Cache, CacheControl, CacheStatus},
+    executor::{Executor, HttpResponse},
+    har::{self, Har},
+    history::{History, HistoryEntry},
+    record::{self, Exchange},
+    spec::RequestSpec,
+};
+
+// 命令行发送请求的流程：按 --cache 使用缓存，发送后写历史记录、--record 录制和 --har
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub executor: Executor,
+    // 只用于GET请求，None 时不使用缓存
+    pub cache: Option<Cache>,
+    // None 时不保存历史记录
+    pub history: Option<History>,
+    // 历史记录里隐藏 Authorization 等敏感请求头
+    pub redact: bool,
+    pub record: Option<PathBuf>,
+    pub har: Option<PathBuf>,
+    // 在标准错误上打印录制文件、翻页等细节
+    pub verbose: bool,
+}
+
+impl Session {
+    pub fn new(executor: Executor) -> Self {
+        Self {
+            executor,
+            cache: None,
+            history: None,
+            redact: true,
+            record: None,
+            har: None,
+            verbose: false,
+        }
+    }
+
+    // 发送请求，并把请求和响应写进历史记录、录制目录和HAR文件
+    // 直接使用缓存时没有发送请求，什么都不写
+    pub async fn send(&self, spec: &RequestSpec) -> Result<HttpResponse> {
+        // 请求里带 Cache-Control: no-store 时不读也不写缓存
+        let cache = self
+            .cache
+            .as_ref()
+            .filter(|_| spec.method == "GET" && !CacheControl::from_request(spec).no_store);
+        let resp = match cache {
+            Some(cache) => match cache.send(&self.executor, spec).await? {
+                (resp, CacheStatus::Fresh) => {
+                    eprintln!("{}\n", "cache: hit (fresh)".yellow());
+                    return Ok(resp);
+                }
+                (resp, CacheStatus::Revalidated) => {
+                    eprintln!("{}\n", "cache: hit (304 Not Modified)".yellow());
+                    resp
+                }
+                (resp, CacheStatus::Miss) => resp,
+            },
+            None => self.executor.send(spec).await?,
+        };
+
+        // 历史记录写入失败不影响请求本身，只打印警告
+        if let Some(ref history) = self.history {
+            if let Err(e) = history.append(&HistoryEntry::new(spec, &resp, self.redact)) {
+                eprintln!("{}", format!("warning: failed to write history: {}", e).yellow());
+            }
+        }
+
+        if let Some(ref dir) = self.record {
+            let file = record::save(dir, &Exchange::new(spec, &resp))?;
+            if self.verbose {
+                eprintln!("{}\n", format!("recorded to {}", file.display()).yellow());
+            }
+        }
+
+        if let Some(ref path) = self.har {
+            Har::append(path, har::Entry::new(spec, &resp)?)?;
+        }
+
+        Ok(resp)
+    }
+}
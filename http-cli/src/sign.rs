@@ -0,0 +1,457 @@
+use std::fmt::Debug;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, HOST},
+    Method, Url,
+};
+use ring::{digest, hmac};
+use serde::Deserialize;
+
+use crate::{
+    spec::{host, path_and_query},
+    template::Vars,
+};
+
+// 要签名的请求，签名结果写回请求头
+// body 是实际发送的字节（压缩后），从磁盘流式上传的请求体为None
+pub struct SignRequest<'a> {
+    pub method: &'a Method,
+    pub url: &'a Url,
+    pub headers: &'a mut HeaderMap,
+    pub body: Option<&'a [u8]>,
+}
+
+// 发送前对请求签名的钩子
+pub trait Signer: Debug + Send + Sync {
+    fn sign(&self, req: &mut SignRequest) -> Result<()>;
+
+    // 签名使用的身份，不同身份的响应分开缓存；默认是整个配置，只用来计算哈希
+    fn identity(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+// 配置文件中的签名配置，例如：
+// [signers.gateway]
+// type = "hmac"
+// secret = "${GATEWAY_SECRET}"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SignerConfig {
+    Hmac(HmacSigner),
+    AwsSigv4(AwsSigV4),
+}
+
+impl SignerConfig {
+    // 替换配置中的模板变量，密钥一般通过 ${ENV} 从环境变量读取
+    pub fn render(&self, vars: &Vars) -> Result<Self> {
+        Ok(match self {
+            SignerConfig::Hmac(s) => SignerConfig::Hmac(HmacSigner {
+                secret: vars.render(&s.secret)?,
+                key_id: s.key_id.as_deref().map(|k| vars.render(k)).transpose()?,
+                ..s.clone()
+            }),
+            SignerConfig::AwsSigv4(s) => SignerConfig::AwsSigv4(AwsSigV4 {
+                access_key: vars.render(&s.access_key)?,
+                secret_key: vars.render(&s.secret_key)?,
+                session_token: s.session_token.as_deref().map(|t| vars.render(t)).transpose()?,
+                ..s.clone()
+            }),
+        })
+    }
+
+    pub fn signer(&self) -> Box<dyn Signer> {
+        match self {
+            SignerConfig::Hmac(s) => Box::new(s.clone()),
+            SignerConfig::AwsSigv4(s) => Box::new(s.clone()),
+        }
+    }
+}
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn insert(headers: &mut HeaderMap, name: &str, value: &str) -> Result<()> {
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("invalid header name {}: {}", name, e))?;
+    headers.insert(name, HeaderValue::from_str(value)?);
+    Ok(())
+}
+
+// HMAC-SHA256 签名，签名的内容由 canonical 模板生成，支持的占位符：
+// {method} {path}（包含查询参数） {host} {timestamp}（unix秒） {body_sha256} {header:Name}
+// {header:Name} 引用的请求头必须存在，包括 User-Agent 等默认请求头
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HmacSigner {
+    pub secret: String,
+    #[serde(default)]
+    pub key_id: Option<String>,
+    #[serde(default = "HmacSigner::default_signature_header")]
+    pub signature_header: String,
+    #[serde(default = "HmacSigner::default_timestamp_header")]
+    pub timestamp_header: String,
+    #[serde(default = "HmacSigner::default_key_id_header")]
+    pub key_id_header: String,
+    #[serde(default = "HmacSigner::default_canonical")]
+    pub canonical: String,
+}
+
+impl HmacSigner {
+    fn default_signature_header() -> String {
+        "X-Signature".to_string()
+    }
+
+    fn default_timestamp_header() -> String {
+        "X-Timestamp".to_string()
+    }
+
+    fn default_key_id_header() -> String {
+        "X-Key-Id".to_string()
+    }
+
+    fn default_canonical() -> String {
+        "{method}\n{path}\n{timestamp}\n{body_sha256}".to_string()
+    }
+
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.to_string(),
+            key_id: None,
+            signature_header: Self::default_signature_header(),
+            timestamp_header: Self::default_timestamp_header(),
+            key_id_header: Self::default_key_id_header(),
+            canonical: Self::default_canonical(),
+        }
+    }
+
+    // 按模板生成要签名的字符串
+    pub fn canonical_string(&self, req: &SignRequest, timestamp: i64) -> Result<String> {
+        let mut out = String::new();
+        let mut rest = self.canonical.as_str();
+
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("unclosed {{ in canonical string {}", self.canonical))?;
+            let name = &rest[start + 1..start + end];
+
+            let value = match name {
+                "method" => req.method.to_string(),
+                "path" => path_and_query(req.url),
+                "host" => host(req.url)?,
+                "timestamp" => timestamp.to_string(),
+                "body_sha256" => match req.body {
+                    Some(body) => sha256_hex(body),
+                    None => return Err(anyhow!("{{body_sha256}} cannot be computed for a body streamed from disk")),
+                },
+                _ => match name.strip_prefix("header:") {
+                    Some(header) => req
+                        .headers
+                        .get(header)
+                        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+                        .ok_or_else(|| anyhow!("header {} referenced by the canonical string is not set", header))?,
+                    None => return Err(anyhow!("unknown placeholder {{{}}} in canonical string", name)),
+                },
+            };
+            out.push_str(&value);
+
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+
+        Ok(out)
+    }
+
+    pub fn sign_at(&self, req: &mut SignRequest, timestamp: i64) -> Result<()> {
+        // 先写入时间戳，canonical 模板里可以通过 {header:X-Timestamp} 引用
+        insert(req.headers, &self.timestamp_header, &timestamp.to_string())?;
+        if let Some(ref key_id) = self.key_id {
+            insert(req.headers, &self.key_id_header, key_id)?;
+        }
+
+        let canonical = self.canonical_string(req, timestamp)?;
+        let signature = hex(&hmac_sha256(self.secret.as_bytes(), canonical.as_bytes()));
+        insert(req.headers, &self.signature_header, &signature)
+    }
+}
+
+impl Signer for HmacSigner {
+    fn sign(&self, req: &mut SignRequest) -> Result<()> {
+        self.sign_at(req, Utc::now().timestamp())
+    }
+}
+
+// AWS Signature Version 4，可以用于S3以及MinIO等兼容S3的服务
+// service 为 s3 时会加上 x-amz-content-sha256 请求头，路径只编码一次，其它服务的路径要编码两次
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AwsSigV4 {
+    pub access_key: String,
+    pub secret_key: String,
+    #[serde(default)]
+    pub session_token: Option<String>,
+    #[serde(default = "AwsSigV4::default_region")]
+    pub region: String,
+    #[serde(default = "AwsSigV4::default_service")]
+    pub service: String,
+}
+
+// AWS要求除了 A-Z a-z 0-9 - _ . ~ 以外的字符都要编码
+const AWS_ENCODE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+const AWS_ENCODE_PATH: &AsciiSet = &AWS_ENCODE.remove(b'/');
+
+impl AwsSigV4 {
+    fn default_region() -> String {
+        "us-east-1".to_string()
+    }
+
+    fn default_service() -> String {
+        "s3".to_string()
+    }
+
+    pub fn new(access_key: &str, secret_key: &str, region: &str, service: &str) -> Self {
+        Self {
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            session_token: None,
+            region: region.to_string(),
+            service: service.to_string(),
+        }
+    }
+
+    // 规范化请求，返回 (规范请求, 参与签名的请求头)
+    fn canonical_request(&self, req: &SignRequest, payload_hash: &str) -> (String, String) {
+        let path = percent_decode_str(req.url.path()).decode_utf8_lossy();
+        let encoded = utf8_percent_encode(&path, AWS_ENCODE_PATH).to_string();
+        let canonical_uri = if self.service == "s3" {
+            encoded
+        } else {
+            utf8_percent_encode(&encoded, AWS_ENCODE_PATH).to_string()
+        };
+
+        let mut query: Vec<(String, String)> = req
+            .url
+            .query_pairs()
+            .map(|(k, v)| {
+                (
+                    utf8_percent_encode(&k, AWS_ENCODE).to_string(),
+                    utf8_percent_encode(&v, AWS_ENCODE).to_string(),
+                )
+            })
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        // 只签 host、content-type 和 x-amz-* 请求头，其它请求头可能在转发时被修改
+        let mut headers: Vec<(String, String)> = req
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                name == HOST.as_str() || name == CONTENT_TYPE.as_str() || name.starts_with("x-amz-")
+            })
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                (name.as_str().to_string(), value.split_whitespace().collect::<Vec<_>>().join(" "))
+            })
+            .collect();
+        headers.sort();
+
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect();
+        let signed_headers = headers.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(";");
+
+        let canonical = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            req.method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+        (canonical, signed_headers)
+    }
+
+    pub fn sign_at(&self, req: &mut SignRequest, now: DateTime<Utc>) -> Result<()> {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        // 流式上传时不对请求体签名
+        let payload_hash = match req.body {
+            Some(body) => sha256_hex(body),
+            None => "UNSIGNED-PAYLOAD".to_string(),
+        };
+
+        if !req.headers.contains_key(HOST) {
+            insert(req.headers, HOST.as_str(), &host(req.url)?)?;
+        }
+        insert(req.headers, "x-amz-date", &amz_date)?;
+        if self.service == "s3" {
+            insert(req.headers, "x-amz-content-sha256", &payload_hash)?;
+        }
+        if let Some(ref token) = self.session_token {
+            insert(req.headers, "x-amz-security-token", token)?;
+        }
+
+        let (canonical, signed_headers) = self.canonical_request(req, &payload_hash);
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, sha256_hex(canonical.as_bytes()));
+
+        let key = [self.region.as_str(), self.service.as_str(), "aws4_request"]
+            .iter()
+            .fold(
+                hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes()),
+                |key, part| hmac_sha256(&key, part.as_bytes()),
+            );
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+        req.headers.insert(AUTHORIZATION, HeaderValue::from_str(&authorization)?);
+
+        Ok(())
+    }
+}
+
+impl Signer for AwsSigV4 {
+    fn sign(&self, req: &mut SignRequest) -> Result<()> {
+        self.sign_at(req, Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn aws_sigv4_works() {
+        // AWS文档中 IAM ListUsers 的签名示例
+        let url = Url::parse("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/x-www-form-urlencoded; charset=utf-8"));
+        let mut req = SignRequest {
+            method: &Method::GET,
+            url: &url,
+            headers: &mut headers,
+            body: Some(b""),
+        };
+
+        let signer = AwsSigV4::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "us-east-1", "iam");
+        signer.sign_at(&mut req, Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap()).unwrap();
+
+        assert_eq!(headers["host"], "iam.amazonaws.com");
+        assert_eq!(headers["x-amz-date"], "20150830T123600Z");
+        assert_eq!(
+            headers[AUTHORIZATION],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+        assert!(headers.get("x-amz-content-sha256").is_none());
+
+        // 路径中的特殊字符：s3 只编码一次，其它服务编码两次
+        let url = Url::parse("https://example.com/a%20b/c").unwrap();
+        let req = SignRequest {
+            method: &Method::GET,
+            url: &url,
+            headers: &mut HeaderMap::new(),
+            body: Some(b""),
+        };
+        assert!(signer.canonical_request(&req, "").0.starts_with("GET\n/a%2520b/c\n"));
+        let s3 = AwsSigV4::new("AKIDEXAMPLE", "secret", "us-east-1", "s3");
+        assert!(s3.canonical_request(&req, "").0.starts_with("GET\n/a%20b/c\n"));
+
+        // s3 需要带上请求体的哈希
+        let url = Url::parse("http://localhost:9000/bucket/a%20b.txt").unwrap();
+        let mut headers = HeaderMap::new();
+        let mut req = SignRequest {
+            method: &Method::PUT,
+            url: &url,
+            headers: &mut headers,
+            body: Some(b"hello"),
+        };
+        AwsSigV4::new("minioadmin", "minioadmin", "us-east-1", "s3").sign(&mut req).unwrap();
+        assert_eq!(headers["host"], "localhost:9000");
+        assert_eq!(headers["x-amz-content-sha256"], sha256_hex(b"hello"));
+        assert!(headers[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .contains("SignedHeaders=host;x-amz-content-sha256;x-amz-date"));
+    }
+
+    #[test]
+    fn hmac_works() {
+        let url = Url::parse("http://gateway.local:8080/api/users?page=2").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("r1"));
+        let mut req = SignRequest {
+            method: &Method::POST,
+            url: &url,
+            headers: &mut headers,
+            body: Some(b"{}"),
+        };
+
+        let mut signer = HmacSigner::new("secret");
+        signer.key_id = Some("cli".to_string());
+        assert_eq!(
+            signer.canonical_string(&req, 1700000000).unwrap(),
+            format!("POST\n/api/users?page=2\n1700000000\n{}", sha256_hex(b"{}"))
+        );
+
+        signer.canonical = "{method} {host} {header:X-Request-Id}".to_string();
+        assert_eq!(signer.canonical_string(&req, 0).unwrap(), "POST gateway.local:8080 r1");
+        signer.canonical = "{header:X-Missing}".to_string();
+        assert!(signer.canonical_string(&req, 0).is_err());
+        signer.canonical = "{method} {host} {header:X-Request-Id}".to_string();
+
+        signer.signature_header = "X-Gateway-Signature".to_string();
+        signer.sign_at(&mut req, 1700000000).unwrap();
+        assert_eq!(headers["x-timestamp"], "1700000000");
+        assert_eq!(headers["x-key-id"], "cli");
+        assert_eq!(
+            headers["x-gateway-signature"],
+            hex(&hmac_sha256(b"secret", b"POST gateway.local:8080 r1")).as_str()
+        );
+
+        signer.canonical = "{unknown}".to_string();
+        let mut req = SignRequest {
+            method: &Method::GET,
+            url: &url,
+            headers: &mut HeaderMap::new(),
+            body: Some(b""),
+        };
+        assert!(signer.sign_at(&mut req, 0).is_err());
+    }
+
+    #[test]
+    fn signer_config_works() {
+        let config: SignerConfig = toml::from_str(
+            "type = \"aws-sigv4\"\naccess_key = \"minioadmin\"\nsecret_key = \"${HTTP_CLI_SIGN_TEST_SECRET}\"\n",
+        )
+        .unwrap();
+        std::env::set_var("HTTP_CLI_SIGN_TEST_SECRET", "s3cr3t");
+
+        let config = config.render(&Vars::default()).unwrap();
+        assert_eq!(
+            config,
+            SignerConfig::AwsSigv4(AwsSigV4::new("minioadmin", "s3cr3t", "us-east-1", "s3"))
+        );
+
+        let config: SignerConfig = toml::from_str("type = \"hmac\"\nsecret = \"abc\"\n").unwrap();
+        assert_eq!(config, SignerConfig::Hmac(HmacSigner::new("abc")));
+        assert!(toml::from_str::<SignerConfig>("type = \"unknown\"\n").is_err());
+    }
+}
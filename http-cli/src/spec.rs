@@ -0,0 +1,604 @@
+use std::{path::{Path, PathBuf}, str::FromStr};
+use anyhow::{anyhow, Result};
+use reqwest::{header::HeaderName, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::protocol::Protocol;
+
+// 一个请求的完整描述，可以序列化保存，也可以在其它rust工具里直接构造
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestSpec {
+    pub method: String,
+    pub url: String,
+    // 请求头，同名的请求头可以出现多次
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Option<Body>,
+    // 是否用gzip压缩请求体
+    #[serde(default)]
+    pub compress: bool,
+    // 发送 Expect: 100-continue，等服务端确认后再上传请求体
+    #[serde(default)]
+    pub expect_continue: bool,
+    // 流式上传时在标准错误输出上显示进度，不会保存
+    #[serde(skip)]
+    pub progress: bool,
+    // 要求使用的http协议版本，由命令行参数决定，不会保存
+    #[serde(skip)]
+    pub protocol: Protocol,
+    // 客户端的默认请求头，例如 User-Agent，请求里没有同名请求头时才发送，不会保存
+    // 发送前合并进请求头，签名和HAR看到的就是实际发送的请求头
+    #[serde(skip)]
+    pub default_headers: Vec<(String, String)>,
+}
+
+// 请求体
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Body {
+    Json(Value),
+    Text(String),
+    // 文件内容，发送时边读边传，不会整个读进内存
+    File(PathBuf),
+    // multipart/form-data，文件部分同样是流式上传
+    Multipart(Vec<Part>),
+}
+
+// multipart 中的一个部分
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Part {
+    Text {
+        name: String,
+        value: String,
+    },
+    File {
+        name: String,
+        path: PathBuf,
+        // 不指定时按扩展名推断
+        #[serde(default)]
+        content_type: Option<String>,
+    },
+}
+
+impl Body {
+    // 编码成要发送的字节，文件和multipart需要流式上传，见 upload 模块
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(match self {
+            Body::Json(v) => serde_json::to_vec(v)?,
+            Body::Text(s) => s.as_bytes().to_vec(),
+            Body::File(_) | Body::Multipart(_) => return Err(anyhow!("{} is streamed from disk", self.describe())),
+        })
+    }
+
+    // 是否需要流式上传
+    pub fn is_streamed(&self) -> bool {
+        matches!(self, Body::File(_) | Body::Multipart(_))
+    }
+
+    // 没有显式指定 Content-Type 时使用的默认值，multipart 的 boundary 在发送时补上
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Body::Json(_) => mime::APPLICATION_JSON.as_ref(),
+            Body::Text(_) => mime::TEXT_PLAIN_UTF_8.as_ref(),
+            Body::File(path) => guess_content_type(path),
+            Body::Multipart(_) => mime::MULTIPART_FORM_DATA.as_ref(),
+        }
+    }
+
+    // 流式请求体的简短描述，用于历史记录、HAR等不保存文件内容的地方
+    pub fn describe(&self) -> String {
+        match self {
+            Body::File(path) => format!("<file {}>", path.display()),
+            Body::Multipart(parts) => {
+                let names: Vec<&str> = parts
+                    .iter()
+                    .map(|p| match p {
+                        Part::Text { name, .. } | Part::File { name, .. } => name.as_str(),
+                    })
+                    .collect();
+                format!("<multipart {}>", names.join(", "))
+            }
+            Body::Json(_) | Body::Text(_) => String::from_utf8_lossy(&self.to_bytes().unwrap_or_default()).into_owned(),
+        }
+    }
+}
+
+// 按扩展名推断文件的类型
+pub fn guess_content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "json" => "application/json",
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        _ => "application/octet-stream",
+    }
+}
+
+impl RequestSpec {
+    pub fn new(method: &str, url: &str) -> Self {
+        Self {
+            method: method.to_uppercase(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            compress: false,
+            expect_continue: false,
+            progress: false,
+            protocol: Protocol::Auto,
+            default_headers: Vec::new(),
+        }
+    }
+
+    pub fn get(url: &str) -> Self {
+        Self::new("GET", url)
+    }
+
+    pub fn post(url: &str) -> Self {
+        Self::new("POST", url)
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn json(mut self, body: Value) -> Self {
+        self.body = Some(Body::Json(body));
+        self
+    }
+
+    pub fn body(mut self, body: Body) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    pub fn expect_continue(mut self, expect_continue: bool) -> Self {
+        self.expect_continue = expect_continue;
+        self
+    }
+
+    // 按名字查找请求头（不区分大小写）
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// key-value对
+#[derive(Debug, Clone, PartialEq)]
+pub struct KvPair {
+    pub k: String,
+    pub v: String,
+    // 用 k:=v 写的值，v 按json解析，例如 age:=30、tags:=[1,2]
+    pub typed: bool,
+}
+
+// KvPair实现FromStr
+impl FromStr for KvPair {
+    type Err = anyhow::Error;
+
+    // 实现from_str方法
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // 根据第一个=分割，值里面可以再出现=
+        let (k, v) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!(format!("failed to parse {}", s)))?;
+
+        // key以:结尾说明写的是 k:=v
+        let (k, typed) = match k.strip_suffix(':') {
+            Some(k) => (k, true),
+            None => (k, false),
+        };
+
+        Ok(Self {
+            k: k.to_string(),
+            v: v.to_string(),
+            typed,
+        })
+    }
+}
+
+impl KvPair {
+    // 转成json值：k:=v 严格按json解析，infer为true时推断数字、布尔和null，其它情况都是字符串
+    pub fn value(&self, infer: bool) -> Result<Value> {
+        if self.typed {
+            let value: Value = serde_json::from_str(&self.v).map_err(|e| anyhow!("invalid json value for {}: {}", self.k, e))?;
+            // 超出 i64/u64 的整数会变成浮点数，丢失精度，不能静默发送
+            if matches!(value, Value::Number(ref n) if n.is_f64()) && self.v.trim().bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+                return Err(anyhow!("{} is too large for a json integer, use {}={} to send it as a string", self.v, self.k, self.v));
+            }
+            return Ok(value);
+        }
+
+        Ok(if infer { infer_value(&self.v) } else { Value::String(self.v.clone()) })
+    }
+}
+
+// 推断字符串的类型，只处理数字、布尔和null，数组和对象需要用 := 显式指定
+// 数字转回字符串和原来不同时（例如超出 i64/u64 的id、1.50）保留为字符串，避免丢失精度
+pub fn infer_value(s: &str) -> Value {
+    match serde_json::from_str::<Value>(s) {
+        Ok(Value::Number(n)) if n.to_string() != s => Value::String(s.to_string()),
+        Ok(v @ (Value::Number(_) | Value::Bool(_) | Value::Null)) => v,
+        _ => Value::String(s.to_string()),
+    }
+}
+
+// 因为KvPair 实现了 FromStr，这里可以直接 s.parse() 得到 KvPair
+pub fn parse_kv_pair(s: &str) -> Result<KvPair> {
+    s.parse()
+}
+
+// 把多个KvPair组装成json对象请求体
+pub fn json_body(pairs: &[KvPair], infer: bool) -> Result<Value> {
+    let mut body = Map::new();
+
+    for pair in pairs.iter() {
+        body.insert(pair.k.clone(), pair.value(infer)?);
+    }
+
+    Ok(Value::Object(body))
+}
+
+// 命令行里的请求体参数
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestItem {
+    // k=v 或者 k:=json
+    Field(KvPair),
+    // field@path，作为multipart的文件部分上传，可以用 field@path;type=image/png 指定类型
+    File {
+        name: String,
+        path: String,
+        content_type: Option<String>,
+    },
+    // @path，整个文件作为请求体
+    Body(String),
+}
+
+impl FromStr for RequestItem {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix('@') {
+            if path.is_empty() {
+                return Err(anyhow!("missing file path in {}", s));
+            }
+            return Ok(RequestItem::Body(path.to_string()));
+        }
+
+        // 先出现的分隔符决定类型，例如 email=a@b.c 是普通字段
+        match (s.find('@'), s.find('=')) {
+            (Some(at), eq) if eq.map(|eq| at < eq).unwrap_or(true) => {
+                let (name, path) = (&s[..at], &s[at + 1..]);
+                if name.is_empty() || path.is_empty() {
+                    return Err(anyhow!("failed to parse {}, expected field@path", s));
+                }
+                let (path, content_type) = match path.rsplit_once(";type=") {
+                    Some((path, t)) => (path, Some(t.to_string())),
+                    None => (path, None),
+                };
+                Ok(RequestItem::File {
+                    name: name.to_string(),
+                    path: path.to_string(),
+                    content_type,
+                })
+            }
+            _ => Ok(RequestItem::Field(s.parse()?)),
+        }
+    }
+}
+
+pub fn parse_request_item(s: &str) -> Result<RequestItem> {
+    s.parse()
+}
+
+// 按命令行参数组装请求体：
+// @path 时整个文件作为请求体；有 field@path 或者 multipart 为true时发送multipart；否则发送json对象
+pub fn request_body(items: &[RequestItem], multipart: bool, infer: bool) -> Result<Body> {
+    let files: Vec<&String> = items
+        .iter()
+        .filter_map(|item| match item {
+            RequestItem::Body(path) => Some(path),
+            _ => None,
+        })
+        .collect();
+    if let Some(path) = files.first() {
+        if items.len() > 1 || multipart {
+            return Err(anyhow!("@{} uploads the whole file as the body and cannot be combined with other fields", path));
+        }
+        return Ok(Body::File(PathBuf::from(path)));
+    }
+
+    let has_files = items.iter().any(|item| matches!(item, RequestItem::File { .. }));
+    if !multipart && !has_files {
+        let pairs: Vec<KvPair> = items
+            .iter()
+            .filter_map(|item| match item {
+                RequestItem::Field(pair) => Some(pair.clone()),
+                _ => None,
+            })
+            .collect();
+        return Ok(Body::Json(json_body(&pairs, infer)?));
+    }
+
+    let mut parts = Vec::new();
+    for item in items.iter() {
+        parts.push(match item {
+            // 表单里的值都是文本，json值按json文本发送
+            RequestItem::Field(pair) => Part::Text {
+                name: pair.k.clone(),
+                value: match pair.value(infer)? {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                },
+            },
+            RequestItem::File { name, path, content_type } => Part::File {
+                name: name.clone(),
+                path: PathBuf::from(path),
+                content_type: content_type.clone(),
+            },
+            RequestItem::Body(_) => unreachable!(),
+        });
+    }
+
+    Ok(Body::Multipart(parts))
+}
+
+// 解析 "Name: value" 格式的请求头
+pub fn parse_header(s: &str) -> Result<(String, String)> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow!("failed to parse header {}, expected Name: value", s))?;
+    let name = name.trim();
+    HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("invalid header name {}: {}", name, e))?;
+
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+// 解析url的方法，没有写协议时默认使用http
+pub fn parse_url(s: &str) -> Result<String> {
+    parse_url_with_scheme(s, "http")
+}
+
+// 支持httpie的简写：
+// :3000/path => http://localhost:3000/path
+// :/path => http://localhost/path
+// example.com/x => http://example.com/x
+pub fn parse_url_with_scheme(s: &str, scheme: &str) -> Result<String> {
+    let url = match s.strip_prefix(':') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}://localhost{}", scheme, rest),
+        Some(rest) => format!("{}://localhost:{}", scheme, rest),
+        None if has_scheme(s) => s.to_string(),
+        None => format!("{}://{}", scheme, s),
+    };
+
+    let _url: Url = url.parse().map_err(|e| anyhow!("invalid url {}: {}", s, e))?; // 检查下url是否合法
+    Ok(url)
+}
+
+// 请求路径加上查询参数，例如 /api/users?page=2
+pub(crate) fn path_and_query(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+// Host 请求头，非默认端口时带上端口
+pub(crate) fn host(url: &Url) -> Result<String> {
+    let host = url.host_str().ok_or_else(|| anyhow!("url {} has no host", url))?;
+
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+// 判断url是否已经写了协议，例如 http://、https://
+// 不能直接交给Url解析，因为 localhost:8080 会被当成协议为 localhost 的url
+fn has_scheme(s: &str) -> bool {
+    match s.split_once("://") {
+        Some((scheme, _)) => {
+            !scheme.is_empty()
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+// ========================单元测试========================================
+// 仅在运行 cargo test 时才编译
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_url_works() {
+        assert!(parse_url("http://").is_err());
+        assert!(parse_url("http://a b").is_err());
+        assert!(parse_url(":abc").is_err());
+        assert!(parse_url("http://abc.xyz").is_ok());
+        assert!(parse_url("https://httpbin.org/post").is_ok());
+
+        // 写了协议的url保持不变
+        assert_eq!(parse_url("https://httpbin.org/post").unwrap(), "https://httpbin.org/post");
+        assert_eq!(parse_url_with_scheme("http://abc.xyz", "https").unwrap(), "http://abc.xyz");
+
+        // localhost简写
+        assert_eq!(parse_url(":3000/path").unwrap(), "http://localhost:3000/path");
+        assert_eq!(parse_url(":3000").unwrap(), "http://localhost:3000");
+        assert_eq!(parse_url(":/path").unwrap(), "http://localhost/path");
+        assert_eq!(parse_url(":").unwrap(), "http://localhost");
+
+        // 没有协议时补全默认协议
+        assert_eq!(parse_url("abc").unwrap(), "http://abc");
+        assert_eq!(parse_url("example.com/x").unwrap(), "http://example.com/x");
+        assert_eq!(parse_url("localhost:8080/api").unwrap(), "http://localhost:8080/api");
+        assert_eq!(parse_url("example.com/x?next=http://a.b").unwrap(), "http://example.com/x?next=http://a.b");
+        assert_eq!(parse_url_with_scheme("example.com/x", "https").unwrap(), "https://example.com/x");
+        assert_eq!(parse_url_with_scheme(":8443/api", "https").unwrap(), "https://localhost:8443/api");
+    }
+
+    #[test]
+    fn parse_kv_pair_works() {
+        assert!(parse_kv_pair("a").is_err());
+        assert_eq!(
+            parse_kv_pair("a=1").unwrap(),
+            KvPair {
+                k: "a".into(),
+                v: "1".into(),
+                typed: false,
+            }
+        );
+
+        assert_eq!(
+            parse_kv_pair("b=").unwrap(),
+            KvPair {
+                k: "b".into(),
+                v: "".into(),
+                typed: false,
+            }
+        );
+
+        // 值里面可以有=，k:=v 表示值按json解析
+        assert_eq!(parse_kv_pair("q=a=b").unwrap().v, "a=b");
+        assert_eq!(
+            parse_kv_pair("tags:=[1,2]").unwrap(),
+            KvPair {
+                k: "tags".into(),
+                v: "[1,2]".into(),
+                typed: true,
+            }
+        );
+    }
+
+    #[test]
+    fn json_body_works() {
+        let pairs: Vec<KvPair> = ["name=tom", "age=30", "zip:=\"007\"", "active:=true", "tags:=[1,2]", "meta:={\"a\":null}"]
+            .iter()
+            .map(|s| parse_kv_pair(s).unwrap())
+            .collect();
+
+        assert_eq!(
+            json_body(&pairs, false).unwrap(),
+            json!({"name": "tom", "age": "30", "zip": "007", "active": true, "tags": [1, 2], "meta": {"a": null}})
+        );
+
+        let pairs: Vec<KvPair> = ["age=30", "ratio=0.5", "active=false", "none=null", "zip=007", "tags=[1]", "name=tom"]
+            .iter()
+            .map(|s| parse_kv_pair(s).unwrap())
+            .collect();
+        assert_eq!(
+            json_body(&pairs, true).unwrap(),
+            json!({"age": 30, "ratio": 0.5, "active": false, "none": null, "zip": "007", "tags": "[1]", "name": "tom"})
+        );
+
+        assert!(json_body(&[parse_kv_pair("age:=thirty").unwrap()], false).is_err());
+
+        // 超出 i64/u64 的整数不能变成浮点数
+        let id = "12345678901234567890123";
+        assert_eq!(infer_value(id), json!(id));
+        assert_eq!(infer_value("18446744073709551615"), json!(u64::MAX));
+        assert_eq!(infer_value("-42"), json!(-42));
+        assert_eq!(infer_value("1.50"), json!("1.50"));
+        assert!(json_body(&[parse_kv_pair(&format!("id:={}", id)).unwrap()], false).is_err());
+        assert_eq!(json_body(&[parse_kv_pair("ratio:=1.5e3").unwrap()], false).unwrap(), json!({"ratio": 1500.0}));
+    }
+
+    #[test]
+    fn request_body_works() {
+        let items: Vec<RequestItem> = ["name=tom", "email=a@b.c", "age:=30", "avatar@me.png;type=image/webp", "doc@a.pdf"]
+            .iter()
+            .map(|s| parse_request_item(s).unwrap())
+            .collect();
+        assert_eq!(
+            items[3],
+            RequestItem::File {
+                name: "avatar".into(),
+                path: "me.png".into(),
+                content_type: Some("image/webp".into()),
+            }
+        );
+
+        assert_eq!(
+            request_body(&items, false, false).unwrap(),
+            Body::Multipart(vec![
+                Part::Text { name: "name".into(), value: "tom".into() },
+                Part::Text { name: "email".into(), value: "a@b.c".into() },
+                Part::Text { name: "age".into(), value: "30".into() },
+                Part::File { name: "avatar".into(), path: "me.png".into(), content_type: Some("image/webp".into()) },
+                Part::File { name: "doc".into(), path: "a.pdf".into(), content_type: None },
+            ])
+        );
+
+        // 没有文件时默认发送json，multipart为true时发送表单
+        assert_eq!(request_body(&items[..3], false, false).unwrap(), Body::Json(json!({"name": "tom", "email": "a@b.c", "age": 30})));
+        assert!(matches!(request_body(&items[..1], true, false).unwrap(), Body::Multipart(_)));
+
+        let body = parse_request_item("@big.iso").unwrap();
+        assert_eq!(request_body(std::slice::from_ref(&body), false, false).unwrap(), Body::File("big.iso".into()));
+        assert!(request_body(&[body, items[0].clone()], false, false).is_err());
+        assert!(parse_request_item("@").is_err());
+        assert!(parse_request_item("@path").is_ok());
+        assert!(parse_request_item("field@").is_err());
+
+        assert_eq!(Body::File("a/b.JSON".into()).content_type(), "application/json");
+        assert_eq!(Body::File("a/b".into()).content_type(), "application/octet-stream");
+    }
+
+    #[test]
+    fn parse_header_works() {
+        assert_eq!(
+            parse_header("Authorization: Bearer abc").unwrap(),
+            ("Authorization".to_string(), "Bearer abc".to_string())
+        );
+        assert_eq!(parse_header("X-Empty:").unwrap(), ("X-Empty".to_string(), "".to_string()));
+        assert!(parse_header("no-colon").is_err());
+        assert!(parse_header("bad name: x").is_err());
+    }
+
+    #[test]
+    fn request_spec_serde_works() {
+        let spec = RequestSpec::post("http://localhost:3000/users")
+            .header("X-Token", "abc")
+            .json(json_body(&[parse_kv_pair("a=1").unwrap()], false).unwrap())
+            .compress(true);
+
+        let s = serde_json::to_string(&spec).unwrap();
+        assert_eq!(serde_json::from_str::<RequestSpec>(&s).unwrap(), spec);
+        assert_eq!(spec.header_value("x-token"), Some("abc"));
+        assert_eq!(spec.body, Some(Body::Json(json!({"a": "1"}))));
+
+        // 省略的字段使用默认值
+        let spec: RequestSpec = serde_json::from_str(r#"{"method": "GET", "url": "http://a.b"}"#).unwrap();
+        assert_eq!(spec, RequestSpec::get("http://a.b"));
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, env, fs, path::{Path, PathBuf}};
+use anyhow::{anyhow, Result};
+
+// 模板变量，来自 envs.toml 中的某个环境
+// {{var}} 先查环境里的变量，找不到再查同名的进程环境变量；${VAR} 直接读取进程环境变量
+// {{{{ 和 $${ 是转义，分别输出原样的 {{ 和 ${
+#[derive(Debug, Clone, Default)]
+pub struct Vars {
+    env: Option<String>,
+    vars: HashMap<String, String>,
+}
+
+// 默认的环境配置文件，例如 ~/.config/http-cli/envs.toml
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("http-cli").join("envs.toml"))
+}
+
+impl Vars {
+    pub fn new(env: Option<&str>, vars: HashMap<String, String>) -> Self {
+        Self {
+            env: env.map(|e| e.to_string()),
+            vars,
+        }
+    }
+
+    // 从配置文件中加载某个环境的变量，文件格式：
+    // [dev]
+    // host = "localhost:3000"
+    pub fn load(path: impl AsRef<Path>, env: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+        let envs: toml::Value = toml::from_str(&content).map_err(|e| anyhow!("invalid {}: {}", path.display(), e))?;
+
+        let table = envs
+            .get(env)
+            .and_then(|v| v.as_table())
+            .ok_or_else(|| anyhow!("env {} is not defined in {}", env, path.display()))?;
+
+        let vars = table
+            .iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    toml::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (k.clone(), v)
+            })
+            .collect();
+
+        Ok(Self::new(Some(env), vars))
+    }
+
+    fn lookup(&self, name: &str) -> Result<String> {
+        if let Some(v) = self.vars.get(name) {
+            return Ok(v.clone());
+        }
+
+        env::var(name).map_err(|_| match self.env {
+            Some(ref e) => anyhow!("unknown variable {}: not defined in env {} or the process environment", name, e),
+            None => anyhow!("unknown variable {}: use --env to load a profile, or set the environment variable", name),
+        })
+    }
+
+    // 替换字符串中的 {{var}} 和 ${VAR}
+    pub fn render(&self, s: &str) -> Result<String> {
+        let mut out = String::with_capacity(s.len());
+        let mut rest = s;
+
+        loop {
+            let next = [rest.find("{{"), rest.find("$${"), rest.find("${")].into_iter().flatten().min();
+            let start = match next {
+                Some(start) => start,
+                None => break,
+            };
+            out.push_str(&rest[..start]);
+
+            // 转义，去掉多余的一个 { 或 $
+            if let Some(escape) = ["{{{{", "$${"].into_iter().find(|e| rest[start..].starts_with(e)) {
+                out.push_str(&escape[escape.len() / 2..]);
+                rest = &rest[start + escape.len()..];
+                continue;
+            }
+
+            let (open, close) = if rest[start..].starts_with("{{") { ("{{", "}}") } else { ("${", "}") };
+            let body = &rest[start + open.len()..];
+            let end = body
+                .find(close)
+                .ok_or_else(|| anyhow!("unclosed {} in {}", open, s))?;
+
+            let name = body[..end].trim();
+            if name.is_empty() {
+                return Err(anyhow!("empty variable name in {}", s));
+            }
+            let value = if open == "{{" {
+                self.lookup(name)?
+            } else {
+                env::var(name).map_err(|_| anyhow!("unknown environment variable {} in {}", name, s))?
+            };
+            out.push_str(&value);
+
+            rest = &body[end + close.len()..];
+        }
+        out.push_str(rest);
+
+        Ok(out)
+    }
+}
+
+// 字符串中是否包含模板变量
+pub fn has_template(s: &str) -> bool {
+    s.contains("{{") || s.contains("${")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_works() {
+        env::set_var("HTTP_CLI_TEMPLATE_TEST_TOKEN", "t0k3n");
+
+        let vars = Vars::new(
+            Some("dev"),
+            [("host".to_string(), "localhost:3000".to_string())].into_iter().collect(),
+        );
+
+        assert_eq!(vars.render("{{host}}/api").unwrap(), "localhost:3000/api");
+        assert_eq!(vars.render("http://{{ host }}/a/{{host}}").unwrap(), "http://localhost:3000/a/localhost:3000");
+        assert_eq!(vars.render("Bearer ${HTTP_CLI_TEMPLATE_TEST_TOKEN}").unwrap(), "Bearer t0k3n");
+        // 环境中没有定义时，回退到进程环境变量
+        assert_eq!(vars.render("{{HTTP_CLI_TEMPLATE_TEST_TOKEN}}").unwrap(), "t0k3n");
+        assert_eq!(vars.render("no template").unwrap(), "no template");
+        // 转义的 {{{{ 和 $${ 原样输出
+        assert_eq!(vars.render("{{{{raw}} $${HOME} {{host}}").unwrap(), "{{raw}} ${HOME} localhost:3000");
+
+        let err = vars.render("{{missing}}").unwrap_err().to_string();
+        assert!(err.contains("missing") && err.contains("dev"));
+        assert!(vars.render("${HTTP_CLI_TEMPLATE_TEST_MISSING}").is_err());
+        assert!(vars.render("{{host").is_err());
+        assert!(vars.render("{{ }}").is_err());
+    }
+
+    #[test]
+    fn load_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("envs.toml");
+        fs::write(&path, "[dev]\nhost = \"localhost:3000\"\nport = 3000\n\n[prod]\nhost = \"api.example.com\"\n").unwrap();
+
+        let vars = Vars::load(&path, "dev").unwrap();
+        assert_eq!(vars.render("{{host}}:{{port}}").unwrap(), "localhost:3000:3000");
+
+        let vars = Vars::load(&path, "prod").unwrap();
+        assert_eq!(vars.render("{{host}}").unwrap(), "api.example.com");
+
+        assert!(Vars::load(&path, "staging").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{path::{Path, PathBuf}, time::{Duration, Instant}};
+use anyhow::{anyhow, Result};
+use reqwest::{header::HeaderMap, Url};
+use tokio::net::UnixStream;
+
+use crate::{
+    direct::send_conn,
+    executor::{HttpResponse, Prepared},
+    protocol::Protocol,
+    sign::Signer,
+    spec::RequestSpec,
+};
+
+// 通过Unix domain socket发送http请求，例如Docker的 /var/run/docker.sock
+// url只用来生成 Host 请求头和请求路径，不会做dns解析
+#[derive(Debug, Clone)]
+pub struct UnixClient {
+    path: PathBuf,
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+}
+
+impl UnixClient {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            headers: HeaderMap::new(),
+            timeout: None,
+        }
+    }
+
+    // 默认请求头，请求里有同名的请求头时不会发送
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // 发送请求，读取并解压响应
+    pub async fn execute(&self, spec: &RequestSpec) -> Result<HttpResponse> {
+        self.execute_with(spec, None).await
+    }
+
+    // 和 execute 一样，signer 会在发送前对请求签名
+    pub async fn execute_with(&self, spec: &RequestSpec, signer: Option<&dyn Signer>) -> Result<HttpResponse> {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.send(spec, signer))
+                .await
+                .map_err(|_| anyhow!("request to {} timed out after {:?}", self.path.display(), timeout))?,
+            None => self.send(spec, signer).await,
+        }
+    }
+
+    async fn send(&self, spec: &RequestSpec, signer: Option<&dyn Signer>) -> Result<HttpResponse> {
+        // 只实现了 HTTP/1.1
+        if !matches!(spec.protocol, Protocol::Auto | Protocol::Http1) {
+            return Err(anyhow!("{} is not supported with a unix socket, only HTTP/1.1 is", spec.protocol));
+        }
+        let url = Url::parse(&spec.url).map_err(|e| anyhow!("invalid url {}: {}", spec.url, e))?;
+        let mut prepared = Prepared::new(spec)?;
+        prepared.sign(&spec.url, signer)?;
+
+        let start = Instant::now();
+        let stream = UnixStream::connect(&self.path)
+            .await
+            .map_err(|e| anyhow!("failed to connect to {}: {}", self.path.display(), e))?;
+        send_conn(stream, &prepared, &url, &self.headers, spec.expect_continue, start).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use hyper::{server::conn::Http, service::service_fn, Body as HyperBody, Request, Response};
+    use reqwest::header::{self, HeaderValue};
+    use serde_json::json;
+    use tokio::net::UnixListener;
+
+    // 把收到的请求回显成json
+    async fn echo(req: Request<HyperBody>) -> Result<Response<HyperBody>, Infallible> {
+        let value = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+        let (method, uri, host, agent) = (req.method().to_string(), req.uri().to_string(), value("host"), value("user-agent"));
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+
+        let echo = json!({
+            "method": method,
+            "uri": uri,
+            "host": host,
+            "user_agent": agent,
+            "body": String::from_utf8_lossy(&body),
+        });
+        let mut resp = Response::new(HyperBody::from(echo.to_string()));
+        resp.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        Ok(resp)
+    }
+
+    #[tokio::test]
+    async fn execute_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("http.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(Http::new().serve_connection(stream, service_fn(echo)));
+            }
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("Rust Httpie"));
+        let client = UnixClient::new(&path).default_headers(headers).timeout(Duration::from_secs(5));
+
+        let resp = client.execute(&RequestSpec::get("http://docker/v1.41/containers/json?all=1")).await.unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(
+            resp.json().unwrap(),
+            json!({
+                "method": "GET",
+                "uri": "/v1.41/containers/json?all=1",
+                "host": "docker",
+                "user_agent": "Rust Httpie",
+                "body": "",
+            })
+        );
+
+        // 请求里的请求头优先于默认请求头，端口会带到 Host 里
+        let spec = RequestSpec::post("http://localhost:3000/users")
+            .header("User-Agent", "test")
+            .json(json!({"name": "a"}));
+        let value = client.execute(&spec).await.unwrap().json().unwrap();
+        assert_eq!(value["host"], "localhost:3000");
+        assert_eq!(value["user_agent"], "test");
+        assert_eq!(value["body"], "{\"name\":\"a\"}");
+
+        assert!(UnixClient::new(dir.path().join("missing.sock")).execute(&spec).await.is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures_core::Stream;
+use tokio_util::io::ReaderStream;
+
+use crate::spec::{guess_content_type, Part};
+
+// 流式上传的请求体，由内存中的数据和磁盘上的文件依次拼接而成，文件边读边发送
+#[derive(Debug, Clone)]
+pub struct Upload {
+    pub content_type: String,
+    // 总大小，有无法预先知道大小的文件（例如管道）时为None，这时使用chunked传输
+    pub len: Option<u64>,
+    sources: Vec<Source>,
+    progress: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    Bytes(Bytes),
+    File(PathBuf),
+}
+
+// 普通文件返回大小，管道、设备等返回None
+fn file_len(path: &Path) -> Result<Option<u64>> {
+    let meta = fs::metadata(path).map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+    Ok(if meta.is_file() { Some(meta.len()) } else { None })
+}
+
+// multipart 头部中的字段名、文件名不能包含引号和换行
+fn quote(s: &str) -> String {
+    s.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+impl Upload {
+    pub fn file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Ok(Self {
+            content_type: guess_content_type(path).to_string(),
+            len: file_len(path)?,
+            sources: vec![Source::File(path.to_path_buf())],
+            progress: false,
+        })
+    }
+
+    // multipart/form-data，boundary 按当前时间生成
+    pub fn multipart(parts: &[Part]) -> Result<Self> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        Self::multipart_with_boundary(parts, &format!("http-cli-{:x}", nanos))
+    }
+
+    fn multipart_with_boundary(parts: &[Part], boundary: &str) -> Result<Self> {
+        let mut sources = Vec::new();
+        // 所有文件都是普通文件时才能算出总大小
+        let mut file_lens = Some(0);
+
+        for part in parts.iter() {
+            match part {
+                Part::Text { name, value } => {
+                    let head = format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", boundary, quote(name));
+                    sources.push(Source::Bytes(format!("{}{}\r\n", head, value).into()));
+                }
+                Part::File { name, path, content_type } => {
+                    let filename = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+                    let content_type = content_type.as_deref().unwrap_or_else(|| guess_content_type(path));
+                    sources.push(Source::Bytes(
+                        format!(
+                            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                            boundary,
+                            quote(name),
+                            quote(&filename),
+                            content_type
+                        )
+                        .into(),
+                    ));
+                    file_lens = file_lens.zip(file_len(path)?).map(|(l, f)| l + f);
+                    sources.push(Source::File(path.clone()));
+                    sources.push(Source::Bytes(Bytes::from_static(b"\r\n")));
+                }
+            }
+        }
+        sources.push(Source::Bytes(format!("--{}--\r\n", boundary).into()));
+
+        let bytes_len: u64 = sources
+            .iter()
+            .map(|s| match s {
+                Source::Bytes(bytes) => bytes.len() as u64,
+                Source::File(_) => 0,
+            })
+            .sum();
+
+        Ok(Self {
+            content_type: format!("multipart/form-data; boundary={}", boundary),
+            len: file_lens.map(|l| l + bytes_len),
+            sources,
+            progress: false,
+        })
+    }
+
+    // 上传时在标准错误输出上显示进度条
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    // 打开所有文件，返回请求体的数据流，文件不存在时在这里报错
+    pub fn stream(&self) -> Result<UploadStream> {
+        let mut sources = VecDeque::new();
+        for source in self.sources.iter() {
+            sources.push_back(match source {
+                Source::Bytes(bytes) => Opened::Bytes(bytes.clone()),
+                Source::File(path) => {
+                    let file = fs::File::open(path).map_err(|e| anyhow!("failed to open {}: {}", path.display(), e))?;
+                    Opened::File(ReaderStream::new(tokio::fs::File::from_std(file)))
+                }
+            });
+        }
+
+        Ok(UploadStream {
+            sources,
+            progress: if self.progress { Some(Progress::new(self.len)) } else { None },
+        })
+    }
+}
+
+enum Opened {
+    Bytes(Bytes),
+    File(ReaderStream<tokio::fs::File>),
+}
+
+// 依次读取各部分的数据流
+pub struct UploadStream {
+    sources: VecDeque<Opened>,
+    progress: Option<Progress>,
+}
+
+impl Stream for UploadStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let chunk = match self.sources.front_mut() {
+                None => {
+                    if let Some(ref mut progress) = self.progress {
+                        progress.finish();
+                    }
+                    return Poll::Ready(None);
+                }
+                Some(Opened::Bytes(bytes)) => Some(Ok(std::mem::take(bytes))),
+                Some(Opened::File(stream)) => match Pin::new(stream).poll_next(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(chunk) => chunk,
+                },
+            };
+
+            match chunk {
+                Some(Ok(bytes)) => {
+                    if let Some(Opened::Bytes(_)) = self.sources.front() {
+                        self.sources.pop_front();
+                    }
+                    if bytes.is_empty() {
+                        continue;
+                    }
+                    if let Some(ref mut progress) = self.progress {
+                        progress.add(bytes.len() as u64);
+                    }
+                    return Poll::Ready(Some(Ok(bytes)));
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                // 当前文件读完了
+                None => {
+                    self.sources.pop_front();
+                }
+            }
+        }
+    }
+}
+
+// 标准错误输出上的上传进度条
+struct Progress {
+    total: Option<u64>,
+    sent: u64,
+    start: Instant,
+    drawn: Option<Instant>,
+    done: bool,
+}
+
+const BAR_WIDTH: usize = 30;
+// 两次刷新之间的最短间隔
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+impl Progress {
+    fn new(total: Option<u64>) -> Self {
+        Self {
+            total,
+            sent: 0,
+            start: Instant::now(),
+            drawn: None,
+            done: false,
+        }
+    }
+
+    fn add(&mut self, n: u64) {
+        self.sent += n;
+        if self.drawn.map(|t| t.elapsed() >= REDRAW_INTERVAL).unwrap_or(true) {
+            self.draw();
+        }
+    }
+
+    fn finish(&mut self) {
+        if !self.done {
+            self.done = true;
+            self.draw();
+            eprintln!();
+        }
+    }
+
+    fn draw(&mut self) {
+        self.drawn = Some(Instant::now());
+        let elapsed = self.start.elapsed().as_secs_f64().max(0.001);
+        let rate = format!("{}/s", human_size((self.sent as f64 / elapsed) as u64));
+
+        let line = match self.total {
+            Some(total) if total > 0 => {
+                let ratio = (self.sent as f64 / total as f64).min(1.0);
+                let filled = (ratio * BAR_WIDTH as f64) as usize;
+                format!(
+                    "upload [{}{}] {:>3}% {} / {} {}",
+                    "#".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    (ratio * 100.0) as u32,
+                    human_size(self.sent),
+                    human_size(total),
+                    rate
+                )
+            }
+            _ => format!("upload {} {}", human_size(self.sent), rate),
+        };
+
+        // \x1b[K 清掉上一次更长的输出
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r{}\x1b[K", line);
+        let _ = stderr.flush();
+    }
+}
+
+// 以1024为单位显示大小，例如 1.5 MiB
+fn human_size(n: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = n as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", n)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 把数据流读完
+    async fn collect(mut stream: UploadStream) -> Vec<u8> {
+        let mut body = Vec::new();
+        while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            body.extend_from_slice(&chunk.unwrap());
+        }
+        body
+    }
+
+    #[tokio::test]
+    async fn multipart_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a \"b\".png");
+        fs::write(&path, b"\x89PNG").unwrap();
+
+        let parts = vec![
+            Part::Text { name: "name".into(), value: "tom".into() },
+            Part::File { name: "avatar".into(), path: path.clone(), content_type: None },
+        ];
+        let upload = Upload::multipart_with_boundary(&parts, "XYZ").unwrap();
+        assert_eq!(upload.content_type, "multipart/form-data; boundary=XYZ");
+
+        let body = collect(upload.stream().unwrap()).await;
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            "--XYZ\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\ntom\r\n\
+             --XYZ\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"a %22b%22.png\"\r\nContent-Type: image/png\r\n\r\n\
+             \u{FFFD}PNG\r\n--XYZ--\r\n"
+        );
+        assert_eq!(upload.len, Some(body.len() as u64));
+
+        // 文件不存在时直接报错
+        let missing = Part::File { name: "f".into(), path: dir.path().join("missing"), content_type: None };
+        assert!(Upload::multipart(&[missing]).is_err());
+    }
+
+    #[tokio::test]
+    async fn file_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.bin");
+        // 比 ReaderStream 的缓冲区大，会分成多块读取
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &content).unwrap();
+
+        let upload = Upload::file(&path).unwrap();
+        assert_eq!(upload.len, Some(100_000));
+        assert_eq!(upload.content_type, "application/octet-stream");
+        assert_eq!(collect(upload.stream().unwrap()).await, content);
+
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536 * 1024), "1.5 MiB");
+    }
+}
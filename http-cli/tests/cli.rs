@@ -0,0 +1,311 @@
+// 命令行的集成测试：在进程内启动一个hyper服务端，运行编译好的 http-cli，
+// 检查实际发出的请求和打印到标准输出的内容
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    path::Path,
+    process::Output,
+    sync::{Arc, Mutex},
+};
+use hyper::{
+    header::{CONTENT_TYPE, HeaderValue},
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Request, Response, Server, StatusCode,
+};
+use serde_json::{json, Value};
+use tokio::process::Command;
+
+// 服务端收到的请求
+#[derive(Debug, Clone)]
+struct Received {
+    method: String,
+    uri: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+type Log = Arc<Mutex<Vec<Received>>>;
+
+// 按路径返回不同类型的响应，/echo 把请求体原样返回
+fn respond(req: &Received) -> Response<Body> {
+    let (status, content_type, body) = match req.uri.as_str() {
+        "/json" => (StatusCode::OK, "application/json; charset=utf-8", r#"{"name":"tom"}"#.to_string()),
+        "/html" => (StatusCode::OK, "text/html", "<p>hi</p>".to_string()),
+        "/text" => (StatusCode::OK, "text/plain", "plain text".to_string()),
+        "/problem" => (StatusCode::NOT_FOUND, "application/problem+json", r#"{"title":"not found"}"#.to_string()),
+        "/bad-type" => (StatusCode::OK, "not a mime type", "raw".to_string()),
+        "/echo" => (
+            StatusCode::CREATED,
+            req.headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("text/plain"),
+            String::from_utf8_lossy(&req.body).into_owned(),
+        ),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "text/plain", "boom".to_string()),
+    };
+
+    let mut resp = Response::new(Body::from(body));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+    if req.uri == "/json" {
+        resp.headers_mut().insert("cache-control", HeaderValue::from_static("max-age=60"));
+    }
+    resp
+}
+
+async fn server() -> (SocketAddr, Log) {
+    serve(false).await
+}
+
+// h2_only 为true时只接受明文的 HTTP/2（h2c），否则只接受 HTTP/1
+async fn serve(h2_only: bool) -> (SocketAddr, Log) {
+    let log: Log = Arc::default();
+    let make = {
+        let log = log.clone();
+        make_service_fn(move |_| {
+            let log = log.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let log = log.clone();
+                    async move {
+                        let (parts, body) = req.into_parts();
+                        let received = Received {
+                            method: parts.method.to_string(),
+                            // HTTP/2 的请求里是完整的url，只保留路径和查询参数
+                            uri: parts.uri.path_and_query().map(|p| p.to_string()).unwrap_or_default(),
+                            headers: parts.headers,
+                            body: hyper::body::to_bytes(body).await.unwrap().to_vec(),
+                        };
+                        let resp = respond(&received);
+                        log.lock().unwrap().push(received);
+                        Ok::<_, Infallible>(resp)
+                    }
+                }))
+            }
+        })
+    };
+
+    // http1_only(false)、http2_only(false) 都会恢复成自动识别，只能设置其中一个
+    let builder = Server::bind(&"127.0.0.1:0".parse().unwrap());
+    let server = if h2_only { builder.http2_only(true) } else { builder.http1_only(true) }.serve(make);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    (addr, log)
+}
+
+// 运行 http-cli，配置、历史记录、缓存都放在临时目录里，不影响本机的文件
+async fn run(home: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_http-cli"))
+        .arg("--no-config")
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_DATA_HOME", home.join("data"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("NO_COLOR", "1")
+        .env_remove("RUST_BACKTRACE")
+        .env_remove("HTTP_CLI_THEME")
+        .env_remove("HTTP_CLI_PROXY")
+        .output()
+        .await
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+// 是否带有 syntect 输出的24位颜色
+fn highlighted(s: &str) -> bool {
+    s.contains("\x1b[38;2;")
+}
+
+#[tokio::test]
+async fn get_works() {
+    let (addr, log) = server().await;
+    let home = tempfile::tempdir().unwrap();
+
+    let url = format!("http://{}/json", addr);
+    let out = stdout(&run(home.path(), &["--theme", "none", "-H", "X-Trace: abc", "get", &url]).await);
+
+    // 发出的请求带上了默认请求头和 -H 指定的请求头
+    let req = log.lock().unwrap()[0].clone();
+    assert_eq!((req.method.as_str(), req.uri.as_str()), ("GET", "/json"));
+    assert_eq!(req.headers["x-trace"], "abc");
+    assert_eq!(req.headers["user-agent"], "Rust Httpie");
+    assert_eq!(req.headers["x-powered-by"], "Rust");
+    assert!(req.headers.contains_key("accept-encoding"));
+    assert!(req.body.is_empty());
+
+    // 状态行、响应头、响应体依次输出
+    assert!(out.starts_with("HTTP/1.1 200 OK\n\n"), "{}", out);
+    assert!(out.contains("content-type: \"application/json; charset=utf-8\"\n"), "{}", out);
+    assert!(out.ends_with("\n\n\n{\"name\":\"tom\"}"), "{:?}", out);
+    assert!(!highlighted(&out));
+
+    // 每次请求都写进了历史记录
+    let history = std::fs::read_to_string(home.path().join("data/http-cli/history.jsonl")).unwrap();
+    assert_eq!(history.lines().count(), 1);
+}
+
+#[tokio::test]
+async fn post_works() {
+    let (addr, log) = server().await;
+    let home = tempfile::tempdir().unwrap();
+
+    let url = format!("http://{}/echo", addr);
+    let out = stdout(&run(home.path(), &["--theme", "none", "post", &url, "name=tom", "age:=30", "tags:=[1,2]"]).await);
+
+    let req = log.lock().unwrap()[0].clone();
+    assert_eq!(req.method, "POST");
+    assert_eq!(req.headers["content-type"], "application/json");
+    assert_eq!(req.headers["content-length"], req.body.len().to_string().as_str());
+    let body: Value = serde_json::from_slice(&req.body).unwrap();
+    assert_eq!(body, json!({"name": "tom", "age": 30, "tags": [1, 2]}));
+
+    assert!(out.starts_with("HTTP/1.1 201 Created\n\n"), "{}", out);
+    assert!(out.ends_with(&String::from_utf8(req.body).unwrap()));
+
+    // 参数格式错误时不发送请求
+    let output = run(home.path(), &["post", &url, "tags:=[1,"]).await;
+    assert!(!output.status.success());
+    assert_eq!(log.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn cache_works() {
+    let (addr, log) = server().await;
+    let home = tempfile::tempdir().unwrap();
+    let url = format!("http://{}/json", addr);
+    let history = home.path().join("data/http-cli/history.jsonl");
+
+    stdout(&run(home.path(), &["--cache", "get", &url]).await);
+    assert_eq!(std::fs::read_to_string(&history).unwrap().lines().count(), 1);
+
+    // max-age 之内直接使用缓存：不发请求，缓存状态在标准错误上，不写历史记录
+    let output = run(home.path(), &["--theme", "none", "--cache", "get", &url]).await;
+    let out = stdout(&output);
+    assert!(out.ends_with("{\"name\":\"tom\"}"), "{}", out);
+    assert!(!out.contains("cache: hit"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cache: hit (fresh)"));
+    assert_eq!(log.lock().unwrap().len(), 1);
+    assert_eq!(std::fs::read_to_string(&history).unwrap().lines().count(), 1);
+
+    // 请求带 no-store 时不使用缓存
+    stdout(&run(home.path(), &["--cache", "-H", "Cache-Control: no-store", "get", &url]).await);
+    assert_eq!(log.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn template_works() {
+    let (addr, log) = server().await;
+    let home = tempfile::tempdir().unwrap();
+    let url = format!("http://{}/echo", addr);
+
+    // 没有 --env 时 {{var}} 读取进程环境变量，找不到时报错，不会原样发送
+    stdout(&run(home.path(), &["post", &url, "text={{HOME}} {{{{raw}}"]).await);
+    let body: Value = serde_json::from_slice(&log.lock().unwrap()[0].body).unwrap();
+    assert_eq!(body, json!({"text": format!("{} {{{{raw}}}}", home.path().display())}));
+    let output = run(home.path(), &["post", &url, "text={{raw}}"]).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown variable raw"));
+    assert_eq!(log.lock().unwrap().len(), 1);
+
+    // 指定 --env 时替换变量，转义的部分原样发送
+    let envs = home.path().join("envs.toml");
+    std::fs::write(&envs, "[dev]\nname = \"tom\"\n").unwrap();
+    let args = ["--env", "dev", "--env-file", envs.to_str().unwrap(), "post", &url, "text={{name}} {{{{raw}} $${HOME}"];
+    stdout(&run(home.path(), &args).await);
+    let body: Value = serde_json::from_slice(&log.lock().unwrap()[1].body).unwrap();
+    assert_eq!(body, json!({"text": "tom {{raw}} ${HOME}"}));
+}
+
+#[tokio::test]
+async fn error_status_works() {
+    let (addr, _) = server().await;
+    let home = tempfile::tempdir().unwrap();
+
+    // 4xx、5xx 照常打印，application/problem+json 也按json高亮
+    let out = stdout(&run(home.path(), &["get", &format!("http://{}/problem", addr)]).await);
+    assert!(out.starts_with("HTTP/1.1 404 Not Found\n\n"), "{}", out);
+    assert!(highlighted(&out));
+    assert!(out.ends_with("\x1b[0m"));
+
+    let out = stdout(&run(home.path(), &["get", &format!("http://{}/missing", addr)]).await);
+    assert!(out.starts_with("HTTP/1.1 500 Internal Server Error\n\n"));
+    assert!(out.ends_with("\n\n\nboom\n"));
+
+    // 连不上时退出码非0，并且打印错误
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed = listener.local_addr().unwrap();
+    drop(listener);
+    let output = run(home.path(), &["get", &format!("http://{}/json", closed)]).await;
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "));
+}
+
+#[tokio::test]
+async fn highlight_works() {
+    let (addr, _) = server().await;
+    let home = tempfile::tempdir().unwrap();
+    let get = |path: &str| format!("http://{}{}", addr, path);
+
+    // 带 charset 参数的json、html 都会高亮
+    assert!(highlighted(&stdout(&run(home.path(), &["get", &get("/json")]).await)));
+    assert!(highlighted(&stdout(&run(home.path(), &["get", &get("/html")]).await)));
+    assert!(highlighted(&stdout(&run(home.path(), &["--theme", "InspiredGitHub", "get", &get("/json")]).await)));
+
+    // 其它类型、无法解析的 Content-Type 原样输出
+    let out = stdout(&run(home.path(), &["get", &get("/text")]).await);
+    assert!(!highlighted(&out));
+    assert!(out.ends_with("\n\n\nplain text\n"));
+    let out = stdout(&run(home.path(), &["get", &get("/bad-type")]).await);
+    assert!(out.ends_with("\n\n\nraw\n"), "{}", out);
+
+    // 主题为 none 或者 HTTP_CLI_THEME=none 时不高亮，不存在的主题直接报错
+    assert!(!highlighted(&stdout(&run(home.path(), &["--theme", "none", "get", &get("/html")]).await)));
+    let output = Command::new(env!("CARGO_BIN_EXE_http-cli"))
+        .args(["--no-config", "get", &get("/json")])
+        .env("HTTP_CLI_THEME", "none")
+        .env("HOME", home.path())
+        .env("XDG_DATA_HOME", home.path().join("data"))
+        .output()
+        .await
+        .unwrap();
+    assert!(!highlighted(&stdout(&output)));
+
+    let output = run(home.path(), &["--theme", "no-such-theme", "get", &get("/json")]).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown theme no-such-theme"));
+}
+
+#[tokio::test]
+async fn protocol_works() {
+    let (h1, _) = server().await;
+    let (h2, log) = serve(true).await;
+    let home = tempfile::tempdir().unwrap();
+    let stderr = |output: &Output| {
+        assert!(!output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+
+    let out = stdout(&run(home.path(), &["--http1.1", "get", &format!("http://{}/json", h1)]).await);
+    assert!(out.starts_with("HTTP/1.1 200 OK"));
+
+    // 明文的 h2c 只能用 --http2-prior-knowledge
+    let out = stdout(&run(home.path(), &["--http2-prior-knowledge", "get", &format!("http://{}/json", h2)]).await);
+    assert!(out.starts_with("HTTP/2.0 200 OK"), "{}", out);
+    assert_eq!(log.lock().unwrap().len(), 1);
+
+    let err = stderr(&run(home.path(), &["--http2", "get", &format!("http://{}/json", h2)]).await);
+    assert!(err.contains("--http2 negotiates h2 via TLS ALPN and needs https"), "{}", err);
+
+    // 服务端不支持 h2c 时给出说明
+    let err = stderr(&run(home.path(), &["--http2-prior-knowledge", "get", &format!("http://{}/json", h1)]).await);
+    assert!(err.contains("the server may not speak h2c"), "{}", err);
+
+    // 协议参数只能选一个
+    let err = stderr(&run(home.path(), &["--http1.1", "--http2", "get", &format!("http://{}/json", h1)]).await);
+    assert!(err.contains("cannot be used with"), "{}", err);
+}
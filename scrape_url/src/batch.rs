@@ -0,0 +1,107 @@
+use std::{collections::HashSet, fs, path::Path};
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+
+// 文件名最多保留的字符数，避免超过文件系统的限制
+const MAX_SLUG_LEN: usize = 100;
+
+// 从文件读取url列表，每行一个，忽略空行和 # 开头的注释，重复的url只保留第一个
+pub fn read_urls(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path).with_context(|| format!("读取 {} 失败", path.display()))?;
+
+    let mut seen = HashSet::new();
+    let mut urls = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        check_url(line).with_context(|| format!("{} 第 {} 行", path.display(), i + 1))?;
+        if seen.insert(line) {
+            urls.push(line.to_string());
+        }
+    }
+    Ok(urls)
+}
+
+// 只接受 http 和 https 的url
+pub fn check_url(s: &str) -> Result<()> {
+    let url: Url = s.parse().map_err(|e| anyhow!("无效的url {}: {}", s, e))?;
+    match url.scheme() {
+        "http" | "https" => Ok(()),
+        scheme => Err(anyhow!("不支持的协议 {}，只支持 http 和 https", scheme)),
+    }
+}
+
+// 把url转成文件名：域名、路径、查询参数中的字母数字保留，其它字符都换成 -
+// https://doc.rust-lang.org/book/ch01-00.html => doc-rust-lang-org-book-ch01-00
+pub fn slugify(url: &str) -> String {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let path = path.strip_suffix(".html").or_else(|| path.strip_suffix(".htm")).unwrap_or(path);
+
+    let mut slug = String::new();
+    for c in path.chars().chain(Some('-')).chain(query.chars()) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(MAX_SLUG_LEN);
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() {
+        "index".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+// 给每个url分配一个文件名，重名时按顺序加上 -2、-3 后缀
+pub fn file_names(urls: &[String], extension: &str) -> Vec<String> {
+    let mut used = HashSet::new();
+    urls.iter()
+        .map(|url| {
+            let slug = slugify(url);
+            let mut name = format!("{}.{}", slug, extension);
+            let mut n = 1;
+            while !used.insert(name.clone()) {
+                n += 1;
+                name = format!("{}-{}.{}", slug, n, extension);
+            }
+            name
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugify_works() {
+        assert_eq!(slugify("https://doc.rust-lang.org/book/ch01-00.html"), "doc-rust-lang-org-book-ch01-00");
+        assert_eq!(slugify("https://www.rust-lang.org/"), "www-rust-lang-org");
+        assert_eq!(slugify("http://localhost:8080/a/B_c?page=2#top"), "localhost-8080-a-b-c-page-2");
+        assert!(slugify(&format!("https://a.com/{}", "x".repeat(300))).len() <= MAX_SLUG_LEN);
+
+        let urls = ["https://a.com/x", "https://a.com/x/", "https://a.com/y"].map(String::from);
+        assert_eq!(file_names(&urls, "md"), ["a-com-x.md", "a-com-x-2.md", "a-com-y.md"]);
+    }
+
+    #[test]
+    fn read_urls_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("urls.txt");
+
+        fs::write(&path, "# rust文档\nhttps://a.com/x\n\n  https://a.com/y  \nhttps://a.com/x\n").unwrap();
+        assert_eq!(read_urls(&path).unwrap(), ["https://a.com/x", "https://a.com/y"]);
+
+        // 出错时指出是哪一行
+        fs::write(&path, "https://a.com/x\nftp://a.com/y\n").unwrap();
+        let err = read_urls(&path).unwrap_err();
+        assert_eq!(format!("{:#}", err), format!("{} 第 2 行: 不支持的协议 ftp，只支持 http 和 https", path.display()));
+    }
+}
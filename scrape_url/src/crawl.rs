@@ -0,0 +1,369 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use anyhow::{anyhow, Context, Result};
+use reqwest::{Client, Url};
+use scraper::{Html, Selector};
+use url::Position;
+
+use crate::{
+    extract::Extract,
+    robots::Robots,
+    scrape::{self, Format},
+};
+
+// 抓取范围：域名（包括子域名）和路径前缀
+#[derive(Debug, Clone)]
+pub struct Scope {
+    domain: String,
+    prefix: String,
+}
+
+impl Scope {
+    // 域名默认是起始url的域名，路径前缀默认是 /，即整个站点
+    pub fn new(start: &Url, domain: Option<&str>, prefix: Option<&str>) -> Result<Self> {
+        let domain = match domain {
+            Some(domain) => domain.trim_start_matches('.').to_ascii_lowercase(),
+            None => start.host_str().ok_or_else(|| anyhow!("{} 没有域名", start))?.to_string(),
+        };
+        let prefix = match prefix {
+            Some(prefix) if prefix.starts_with('/') => prefix.to_string(),
+            Some(prefix) => format!("/{}", prefix),
+            None => "/".to_string(),
+        };
+
+        let scope = Self { domain, prefix };
+        if !scope.contains(start) {
+            return Err(anyhow!("起始url {} 不在抓取范围 {}{} 内", start, scope.domain, scope.prefix));
+        }
+        Ok(scope)
+    }
+
+    pub fn contains(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) if matches!(url.scheme(), "http" | "https") => host,
+            _ => return false,
+        };
+        let in_domain = host == self.domain || host.ends_with(&format!(".{}", self.domain));
+        in_domain && url.path().starts_with(&self.prefix)
+    }
+}
+
+// 去掉 #锚点 和空的查询参数，同一个页面只抓取一次
+pub fn normalize(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    if url.query() == Some("") {
+        url.set_query(None);
+    }
+    url
+}
+
+// 相对链接的基准url，有 <base href> 时用它，否则是页面url
+pub fn base_url(doc: &Html, page: &Url) -> Url {
+    let base = Selector::parse("base[href]").unwrap();
+    doc.select(&base)
+        .next()
+        .and_then(|e| page.join(e.value().attr("href")?).ok())
+        .unwrap_or_else(|| page.clone())
+}
+
+// 页面里所有 http、https 的链接，相对链接按 <base> 或者页面url转成绝对url
+pub fn links(html: &str, page: &Url) -> Vec<Url> {
+    let doc = Html::parse_document(html);
+    let base = base_url(&doc, page);
+
+    let anchor = Selector::parse("a[href]").unwrap();
+    doc.select(&anchor)
+        .filter_map(|e| base.join(e.value().attr("href")?.trim()).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(|url| normalize(&url))
+        .collect()
+}
+
+// 按站点结构生成保存的路径：
+// https://a.com/ => a.com/index.md
+// https://a.com/book/ => a.com/book/index.md
+// https://a.com/book/ch01.html?v=2 => a.com/book/ch01-v-2.md
+pub fn mirror_path(url: &Url, extension: &str) -> PathBuf {
+    let mut path = PathBuf::new();
+    let host = url.host_str().unwrap_or("localhost");
+    match url.port() {
+        Some(port) => path.push(format!("{}-{}", host, port)),
+        None => path.push(host),
+    }
+
+    let segments: Vec<&str> = url.path().split('/').filter(|s| !s.is_empty()).collect();
+    let (dirs, file) = match url.path().ends_with('/') {
+        true => (&segments[..], "index"),
+        false => match segments.split_last() {
+            Some((file, dirs)) => (dirs, *file),
+            None => (&segments[..], "index"),
+        },
+    };
+    for dir in dirs {
+        path.push(sanitize(dir));
+    }
+
+    let file = file.strip_suffix(".html").or_else(|| file.strip_suffix(".htm")).unwrap_or(file);
+    let mut name = sanitize(file);
+    if let Some(query) = url.query() {
+        name.push('-');
+        name.push_str(&sanitize(query));
+    }
+    path.push(format!("{}.{}", name, extension));
+    path
+}
+
+// 文件名只保留字母数字和 . _ -，其它字符换成 -
+fn sanitize(s: &str) -> String {
+    let s: String = s
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '-' })
+        .collect();
+    // 不能出现 . 和 .. 这样的目录名
+    if s.chars().all(|c| c == '.') {
+        s.replace('.', "-")
+    } else {
+        s
+    }
+}
+
+// 抓取的结果汇总
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub saved: usize,
+    pub failed: Vec<String>,
+    // robots.txt 不允许抓取的页面数
+    pub blocked: usize,
+}
+
+pub struct Crawler {
+    client: Client,
+    scope: Scope,
+    format: Format,
+    // 只转换正文，链接仍然从整个页面里找
+    pub extract: Extract,
+    output_dir: PathBuf,
+    pub max_depth: usize,
+    // 最多保存的页面数，失败和跳过的页面不算
+    pub max_pages: usize,
+    // 两次请求之间的最小间隔，robots.txt 的 Crawl-delay 更长时用 Crawl-delay
+    pub delay: Duration,
+    // 每个站点的 robots.txt，key 是 scheme://host:port
+    robots: HashMap<String, Robots>,
+}
+
+impl Crawler {
+    pub fn new(client: Client, scope: Scope, format: Format, output_dir: PathBuf) -> Self {
+        Self {
+            client,
+            scope,
+            format,
+            extract: Extract::Auto,
+            output_dir,
+            max_depth: 3,
+            max_pages: 100,
+            delay: Duration::ZERO,
+            robots: HashMap::new(),
+        }
+    }
+
+    // 广度优先抓取，起始页面的深度是0
+    pub async fn run(&mut self, start: &Url) -> Result<Summary> {
+        let start = normalize(start);
+        let mut summary = Summary::default();
+        let mut seen = HashSet::from([start.to_string()]);
+        let mut queue = VecDeque::from([(start, 0)]);
+        let mut fetched = 0;
+
+        while let Some((url, depth)) = queue.pop_front() {
+            if summary.saved >= self.max_pages {
+                eprintln!("已达到最大页面数 {}，停止抓取", self.max_pages);
+                break;
+            }
+
+            let robots = self.robots(&url).await;
+            if !robots.is_allowed(&url[Position::BeforePath..Position::AfterQuery]) {
+                eprintln!("robots.txt 不允许抓取 {}", url);
+                summary.blocked += 1;
+                continue;
+            }
+            let delay = robots.crawl_delay.map_or(self.delay, |d| d.max(self.delay));
+            if fetched > 0 && !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            fetched += 1;
+
+            eprintln!("请求url {}", url);
+            let page = match scrape::fetch_page(&self.client, url.as_str()).await {
+                Ok(page) => page,
+                Err(e) => {
+                    eprintln!("下载错误: {:#}", e);
+                    summary.failed.push(url.to_string());
+                    continue;
+                }
+            };
+
+            // 重定向到范围外或者已经抓取过的页面时跳过
+            let page_url = normalize(&page.url);
+            if page_url != url && (!self.scope.contains(&page_url) || !seen.insert(page_url.to_string())) {
+                eprintln!("跳过 {}，重定向到了 {}", url, page_url);
+                continue;
+            }
+            if !page.is_html() {
+                eprintln!("跳过 {}，不是html", page_url);
+                continue;
+            }
+
+            let path = self.output_dir.join(mirror_path(&page_url, self.format.extension()));
+            let result = scrape::convert(&page.body, &page_url, self.format, &self.extract)
+                .with_context(|| format!("转换 {} 失败", page_url))
+                .and_then(|doc| save(&path, &doc));
+            if let Err(e) = result {
+                eprintln!("保存错误: {:#}", e);
+                summary.failed.push(page_url.to_string());
+                continue;
+            }
+            eprintln!("{} => {}", page_url, path.display());
+            summary.saved += 1;
+
+            if depth < self.max_depth {
+                for link in links(&page.body, &page_url) {
+                    if self.scope.contains(&link) && seen.insert(link.to_string()) {
+                        queue.push_back((link, depth + 1));
+                    }
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    // 每个站点只下载一次 robots.txt
+    // 不存在（4xx）时允许抓取所有页面，服务端错误或者连接失败时按 RFC 9309 不抓取
+    async fn robots(&mut self, url: &Url) -> Robots {
+        let origin = url.origin().ascii_serialization();
+        if let Some(robots) = self.robots.get(&origin) {
+            return robots.clone();
+        }
+
+        let robots_url = format!("{}/robots.txt", origin);
+        let robots = match self.client.get(&robots_url).send().await {
+            Ok(resp) if resp.status().is_success() => match resp.text().await {
+                Ok(content) => Robots::parse(&content, scrape::USER_AGENT),
+                Err(_) => Robots::disallow_all(),
+            },
+            Ok(resp) if resp.status().is_client_error() => Robots::allow_all(),
+            Ok(resp) => {
+                eprintln!("{} 返回了 {}，不抓取这个站点", robots_url, resp.status());
+                Robots::disallow_all()
+            }
+            Err(e) => {
+                eprintln!("下载 {} 失败，不抓取这个站点: {}", robots_url, e);
+                Robots::disallow_all()
+            }
+        };
+        self.robots.insert(origin, robots.clone());
+        robots
+    }
+}
+
+fn save(path: &Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("创建目录 {} 失败", dir.display()))?;
+    }
+    fs::write(path, content).with_context(|| format!("写入 {} 失败", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_works() {
+        let start = Url::parse("https://doc.rust-lang.org/book/").unwrap();
+        let scope = Scope::new(&start, None, Some("book/")).unwrap();
+        assert!(scope.contains(&Url::parse("https://doc.rust-lang.org/book/ch01.html").unwrap()));
+        assert!(!scope.contains(&Url::parse("https://doc.rust-lang.org/std/").unwrap()));
+        assert!(!scope.contains(&Url::parse("https://www.rust-lang.org/book/").unwrap()));
+        assert!(!scope.contains(&Url::parse("mailto:a@rust-lang.org").unwrap()));
+
+        // 指定域名时包括子域名
+        let scope = Scope::new(&start, Some("rust-lang.org"), None).unwrap();
+        assert!(scope.contains(&Url::parse("https://www.rust-lang.org/learn").unwrap()));
+        assert!(!scope.contains(&Url::parse("https://rust-lang.org.evil.com/").unwrap()));
+        assert!(Scope::new(&start, None, Some("/std/")).is_err());
+    }
+
+    #[test]
+    fn links_works() {
+        let page = Url::parse("https://a.com/book/ch01.html").unwrap();
+        let html = r##"<a href="ch02.html#intro">2</a> <a href="/">home</a> <a href="?">q</a>
+            <a href="mailto:a@a.com">mail</a> <a href="#top">top</a> <a name="x">x</a>"##;
+        let found: Vec<String> = links(html, &page).iter().map(|u| u.to_string()).collect();
+        assert_eq!(
+            found,
+            ["https://a.com/book/ch02.html", "https://a.com/", "https://a.com/book/ch01.html", "https://a.com/book/ch01.html"]
+        );
+
+        let html = r#"<head><base href="/docs/"></head><a href="x">x</a>"#;
+        assert_eq!(links(html, &page)[0].as_str(), "https://a.com/docs/x");
+    }
+
+    #[test]
+    fn mirror_path_works() {
+        let path = |url: &str| mirror_path(&Url::parse(url).unwrap(), "md").to_string_lossy().into_owned();
+        assert_eq!(path("https://a.com"), "a.com/index.md");
+        assert_eq!(path("https://a.com/book/"), "a.com/book/index.md");
+        assert_eq!(path("https://a.com/book/ch01.html?v=2"), "a.com/book/ch01-v-2.md");
+        assert_eq!(path("http://localhost:8080/a//b%20c"), "localhost-8080/a/b-20c.md");
+    }
+
+    // 本地的简单http服务，/ 链接到一个404的页面和两个正常页面
+    fn serve() -> Url {
+        use std::{io::{BufRead, BufReader, Write}, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                let (status, body) = match line.split_whitespace().nth(1) {
+                    Some("/") => ("200 OK", r#"<a href="/missing">x</a> <a href="/a">a</a> <a href="/b">b</a>"#),
+                    Some("/a") | Some("/b") => ("200 OK", "<p>page</p>"),
+                    _ => ("404 Not Found", ""),
+                };
+                let resp = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: text/html\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(resp.as_bytes());
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn run_works() {
+        let start = serve();
+        let dir = tempfile::tempdir().unwrap();
+        let scope = Scope::new(&start, None, None).unwrap();
+        let mut crawler = Crawler::new(scrape::client().unwrap(), scope, Format::Markdown, dir.path().to_path_buf());
+        // 失败的页面不算在 max_pages 里
+        crawler.max_pages = 2;
+
+        let summary = crawler.run(&start).await.unwrap();
+        assert_eq!(summary.saved, 2);
+        assert_eq!(summary.failed, vec![format!("{}missing", start)]);
+        assert!(dir.path().join(mirror_path(&start.join("/a").unwrap(), "md")).exists());
+        assert!(!dir.path().join(mirror_path(&start.join("/b").unwrap(), "md")).exists());
+    }
+}
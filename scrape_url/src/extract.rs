@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use anyhow::{anyhow, Result};
+use scraper::{ElementRef, Html, Selector};
+
+// 转换前从页面里取出哪一部分
+#[derive(Debug, Clone)]
+pub enum Extract {
+    // 给页面里的节点打分，找出正文
+    Auto,
+    // 使用 --selector 指定的css选择器，匹配多个时按顺序拼接
+    Selector(Selector),
+    // 整个页面
+    Full,
+}
+
+impl Extract {
+    pub fn selector(css: &str) -> Result<Self> {
+        let selector = Selector::parse(css).map_err(|e| anyhow!("无效的css选择器 {}: {}", css, e))?;
+        Ok(Extract::Selector(selector))
+    }
+
+    // 返回要转换的html片段
+    pub fn apply(&self, html: &str) -> Result<String> {
+        match self {
+            Extract::Auto => Ok(main_content(html)),
+            Extract::Selector(selector) => {
+                let doc = Html::parse_document(html);
+                let parts: Vec<String> = doc.select(selector).map(|e| e.html()).collect();
+                if parts.is_empty() {
+                    return Err(anyhow!("css选择器没有匹配到任何元素"));
+                }
+                Ok(parts.join("\n"))
+            }
+            Extract::Full => Ok(html.to_string()),
+        }
+    }
+}
+
+// 肯定不是正文的标签
+const REMOVED_TAGS: &str = "script, style, noscript, template, iframe, svg, canvas, form, button, nav, header, footer, aside, \
+    [role=navigation], [role=banner], [role=contentinfo], [role=dialog], [aria-hidden=true]";
+// class 或者 id 包含这些词时可能不是正文
+const UNLIKELY: [&str; 16] = [
+    "cookie", "consent", "banner", "gdpr", "popup", "modal", "sidebar", "menu", "nav", "footer", "header",
+    "breadcrumb", "share", "social", "comment", "advert",
+];
+// class 或者 id 包含这些词时很可能是正文
+const POSITIVE: [&str; 8] = ["article", "content", "main", "body", "post", "entry", "text", "doc"];
+// 参与打分的段落，文本太短的不算
+const PARAGRAPHS: &str = "p, pre, td, blockquote, li, dd";
+const MIN_PARAGRAPH_LEN: usize = 25;
+// 和最高分比较的候选数
+const CLOSE_CANDIDATES: usize = 10;
+
+// 仿照 Readability 的做法：
+// 1. 去掉脚本、导航、页头页脚、cookie 提示等肯定不是正文的节点
+// 2. 每个段落按长度和逗号数打分，分数加给父节点，祖父节点加一半，再往上加三分之一
+// 3. 分数乘以 (1 - 链接文字占比)，最高分的节点就是正文
+// 4. 分数接近的节点很多时，改用它们共同的祖先
+// 找不到段落时返回 <body>
+pub fn main_content(html: &str) -> String {
+    let mut doc = Html::parse_document(html);
+    remove_boilerplate(&mut doc);
+
+    let paragraphs = Selector::parse(PARAGRAPHS).unwrap();
+    let mut scores = HashMap::new();
+    for p in doc.select(&paragraphs) {
+        let len = text_len(p);
+        if len < MIN_PARAGRAPH_LEN {
+            continue;
+        }
+        let text: String = p.text().collect();
+        let score = 1.0 + text.matches([',', '，']).count() as f64 + (len as f64 / 100.0).min(3.0);
+
+        let ancestors = p.ancestors().filter_map(ElementRef::wrap).take(3);
+        for (level, node) in ancestors.enumerate() {
+            if matches!(node.value().name(), "html" | "body") {
+                break;
+            }
+            let divider = match level {
+                0 => 1.0,
+                1 => 2.0,
+                _ => 3.0,
+            };
+            *scores.entry(node.id()).or_insert_with(|| initial_score(node)) += score / divider;
+        }
+    }
+
+    let mut ranked: Vec<(ElementRef, f64)> = scores
+        .into_iter()
+        .filter_map(|(id, score)| Some((ElementRef::wrap(doc.tree.get(id)?)?, score)))
+        .map(|(node, score)| (node, score * (1.0 - link_density(node))))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    match ranked.first() {
+        Some(&(best, score)) => {
+            // 分数接近的候选有3个以上时，正文是它们共同的祖先，例如 rustdoc 每个方法的文档是一个块
+            let close: Vec<ElementRef> = ranked[1..]
+                .iter()
+                .take(CLOSE_CANDIDATES)
+                .filter(|(_, s)| *s >= score * 0.75)
+                .map(|(node, _)| *node)
+                .collect();
+            // 取包含最多候选的祖先里最靠下的一个
+            let mut common = (best, 0);
+            if close.len() >= 3 {
+                for ancestor in best.ancestors().filter_map(ElementRef::wrap) {
+                    if matches!(ancestor.value().name(), "html" | "body") {
+                        break;
+                    }
+                    let contained = close.iter().filter(|c| c.ancestors().any(|a| a.id() == ancestor.id())).count();
+                    if contained >= 3 && contained > common.1 {
+                        common = (ancestor, contained);
+                    }
+                }
+            }
+            common.0.html()
+        }
+        None => {
+            let body = Selector::parse("body").unwrap();
+            doc.select(&body).next().map_or_else(|| doc.html(), |body| body.html())
+        }
+    }
+}
+
+fn remove_boilerplate(doc: &mut Html) {
+    let removed = Selector::parse(REMOVED_TAGS).unwrap();
+    let any = Selector::parse("[class], [id]").unwrap();
+
+    let mut ids: Vec<_> = doc.select(&removed).map(|e| e.id()).collect();
+    ids.extend(doc.select(&any).filter(|e| is_unlikely(*e)).map(|e| e.id()));
+    for id in ids {
+        if let Some(mut node) = doc.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+}
+
+// class 和 id 合在一起判断，同时包含正文的词时保留
+// 只检查容器，标题、链接等行内元素不删，例如 mdbook 的 <a class="header">
+fn is_unlikely(e: ElementRef) -> bool {
+    if !matches!(e.value().name(), "div" | "section" | "ul" | "ol" | "dl" | "table" | "p" | "span" | "figure") {
+        return false;
+    }
+    let names = class_and_id(e);
+    UNLIKELY.iter().any(|w| names.contains(w)) && !POSITIVE.iter().any(|w| names.contains(w))
+}
+
+fn class_and_id(e: ElementRef) -> String {
+    let mut names = e.value().attr("class").unwrap_or_default().to_ascii_lowercase();
+    names.push(' ');
+    names.push_str(&e.value().attr("id").unwrap_or_default().to_ascii_lowercase());
+    names
+}
+
+// 按标签和 class、id 给节点一个初始分
+fn initial_score(e: ElementRef) -> f64 {
+    let tag = match e.value().name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    };
+    let names = class_and_id(e);
+    let class = if POSITIVE.iter().any(|w| names.contains(w)) { 25.0 } else { 0.0 };
+    tag + class
+}
+
+fn text_len(e: ElementRef) -> usize {
+    e.text().map(|t| t.trim().chars().count()).sum()
+}
+
+// 链接文字占全部文字的比例，导航、目录接近1
+fn link_density(e: ElementRef) -> f64 {
+    let total = text_len(e);
+    if total == 0 {
+        return 0.0;
+    }
+    let a = Selector::parse("a").unwrap();
+    let links: usize = e.select(&a).map(text_len).sum();
+    links as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r##"<html><body>
+        <div class="cookie-banner"><p>We use cookies to improve your experience, please accept them all.</p></div>
+        <nav><ul><li><a href="/">Home</a></li><li><a href="/docs">Documentation for everything</a></li></ul></nav>
+        <div id="page">
+          <div class="sidebar"><p>Related: <a href="/a">a long link to another page of the site</a></p></div>
+          <div class="rust-doc">
+            <h1><a class="header" href="#ownership">Ownership</a></h1>
+            <p>Ownership is a set of rules that govern how a Rust program manages memory.</p>
+            <p>All programs have to manage the way they use memory, while running, in some way.</p>
+            <pre>let s = String::from("hello");</pre>
+            <script>track()</script>
+          </div>
+        </div>
+        <footer><p>Copyright 2024, all rights reserved, by the Rust team and contributors.</p></footer>
+    </body></html>"##;
+
+    #[test]
+    fn main_content_works() {
+        let html = main_content(PAGE);
+        assert!(html.starts_with(r#"<div class="rust-doc">"#), "{}", html);
+        // 属性的输出顺序不固定，分开检查
+        assert!(html.contains(r##"<h1><a "##) && html.contains(r##"href="#ownership""##));
+        assert!(html.contains(r#"class="header""#) && html.contains(">Ownership</a></h1>"));
+        assert!(html.contains("String::from"));
+        for boilerplate in ["cookies", "Documentation", "Related", "Copyright", "track()"] {
+            assert!(!html.contains(boilerplate), "{}", html);
+        }
+
+        // 没有段落时返回整个 <body>
+        assert_eq!(main_content("<p>short</p>"), "<body><p>short</p></body>");
+    }
+
+    #[test]
+    fn selector_works() {
+        let html = Extract::selector(".rust-doc p").unwrap().apply(PAGE).unwrap();
+        assert_eq!(
+            html,
+            "<p>Ownership is a set of rules that govern how a Rust program manages memory.</p>\n\
+             <p>All programs have to manage the way they use memory, while running, in some way.</p>"
+        );
+
+        assert!(Extract::selector(".missing").unwrap().apply(PAGE).is_err());
+        assert!(Extract::selector("div[").is_err());
+        assert_eq!(Extract::Full.apply(PAGE).unwrap(), PAGE);
+    }
+}
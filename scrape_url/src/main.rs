@@ -1,57 +1,280 @@
-use std::fs;
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+    time::Duration,
+};
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use futures::{stream, StreamExt};
+use reqwest::Url;
 
-// 拉取rust文档html,然后转成markdown的例子
-// 执行 cargo run 会编译后运行 main.rs
+mod batch;
+mod crawl;
+mod extract;
+mod metadata;
+mod robots;
+mod scrape;
 
-// 没有错误处理，unwrap()只关心成功结果
-fn rust_html_to_md() {
-    let url = "https://www.rust-lang.org/";
-    let output = "rust1.md";
+use extract::Extract;
+use scrape::Format;
 
-    println!("请求url {}", url);
+// 下载网页并转成markdown
+// 执行 cargo run -- https://www.rust-lang.org/ -o rust.md 会编译后运行 main.rs
+// 批量下载 cargo run -- --input urls.txt --output-dir docs -j 8
+// 输出元数据、正文和链接 cargo run -- -f json https://www.rust-lang.org/
+// 抓取整个站点 cargo run -- crawl https://doc.rust-lang.org/book/ --prefix /book/ -d docs
+#[derive(Parser, Debug)]
+#[clap(version, about = "下载网页并转换成markdown")]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    // 要下载的url，可以指定多个，按顺序输出
+    #[clap(required_unless_present = "input", validator = batch::check_url)]
+    urls: Vec<String>,
+    // 从文件读取url列表，每行一个，# 开头的是注释
+    #[clap(short, long)]
+    input: Option<PathBuf>,
+    // 输出文件，不指定时输出到标准输出
+    #[clap(short, long, conflicts_with_all = &["input", "output-dir"])]
+    output: Option<PathBuf>,
+    // 每个页面单独保存到这个目录，文件名由url生成，使用 --input 时默认是当前目录
+    #[clap(short = 'd', long)]
+    output_dir: Option<PathBuf>,
+    // 同时进行的请求数
+    #[clap(short = 'j', long, default_value = "4", validator = parse_concurrency)]
+    concurrency: usize,
+    // 输出格式
+    #[clap(short, long, default_value = "markdown", possible_values = Format::NAMES)]
+    format: Format,
+    #[clap(flatten)]
+    extract: ExtractArgs,
+}
 
-    let body = reqwest::blocking::get(url).unwrap().text().unwrap();
+#[derive(Subcommand, Debug)]
+enum Command {
+    // 从起始url开始，跟随范围内的链接抓取页面，按站点的目录结构保存
+    Crawl(Crawl),
+}
 
-    println!("开始转换html为markdown");
-    
-    let md = html2md::parse_html(&body);
+#[derive(Parser, Debug)]
+struct Crawl {
+    // 起始url
+    #[clap(validator = batch::check_url)]
+    url: String,
+    // 保存的目录，下面按 域名/路径 保存每个页面
+    #[clap(short = 'd', long, default_value = ".")]
+    output_dir: PathBuf,
+    // 只抓取这个域名和它的子域名，默认是起始url的域名
+    #[clap(long)]
+    domain: Option<String>,
+    // 只抓取路径以它开头的页面，例如 /book/
+    #[clap(long)]
+    prefix: Option<String>,
+    // 最多跟随几层链接，0 表示只抓取起始页面
+    #[clap(long, default_value = "3")]
+    max_depth: usize,
+    // 最多保存多少个页面，失败和跳过的页面不算
+    #[clap(long, default_value = "100")]
+    max_pages: usize,
+    // 两次请求之间至少间隔多少秒，robots.txt 的 Crawl-delay 更长时用 Crawl-delay
+    #[clap(long, default_value = "0", validator = parse_delay)]
+    delay: f64,
+    // 输出格式
+    #[clap(short, long, default_value = "markdown", possible_values = Format::NAMES)]
+    format: Format,
+    #[clap(flatten)]
+    extract: ExtractArgs,
+}
 
-    fs::write(output, md.as_bytes()).unwrap();
+// 默认自动识别正文，去掉导航、页脚、cookie 提示等
+#[derive(Parser, Debug)]
+struct ExtractArgs {
+    // 只转换匹配这个css选择器的元素，例如 "main article"
+    #[clap(short, long, validator = parse_selector)]
+    selector: Option<String>,
+    // 转换整个页面，不提取正文
+    #[clap(long, conflicts_with = "selector")]
+    full: bool,
+}
 
-    println!("转换后的markdown文件为 {}", output); 
+impl ExtractArgs {
+    fn extract(&self) -> Result<Extract> {
+        match (&self.selector, self.full) {
+            (Some(css), _) => Extract::selector(css),
+            (None, true) => Ok(Extract::Full),
+            (None, false) => Ok(Extract::Auto),
+        }
+    }
 }
 
-// 错误处理
-// main 函数返回一个 Result<T, E>
-fn rust_html_to_md_error() -> Result<(), Box<dyn std::error::Error>> {
-    let url = "https://www.rust-lang.org/";
-    let output = "rust2.md";
+fn parse_selector(s: &str) -> Result<()> {
+    Extract::selector(s).map(|_| ())
+}
 
-    println!("请求url {}", url);
+fn parse_delay(s: &str) -> Result<()> {
+    match s.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok(()),
+        _ => Err(anyhow!("间隔必须是不小于0的秒数")),
+    }
+}
+
+fn parse_concurrency(s: &str) -> Result<()> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(anyhow!("并发数必须是大于0的整数")),
+    }
+}
 
-    // 把unwrap()换成了?，并让函数返回一个Result<T, E>，原先使用unwrap方法只会关心返回成功的结果
-    let body = reqwest::blocking::get(url)?.text()?; 
+impl Opts {
+    // 命令行和 --input 文件里的url，去掉重复的
+    fn urls(&self) -> Result<Vec<String>> {
+        let mut urls = self.urls.clone();
+        if let Some(ref path) = self.input {
+            urls.extend(batch::read_urls(path)?);
+        }
+        let mut seen = HashSet::new();
+        urls.retain(|url| seen.insert(url.clone()));
+        Ok(urls)
+    }
 
-    println!("开始转换html为markdown");
+    fn output_dir(&self) -> Option<PathBuf> {
+        match (&self.output_dir, &self.input) {
+            (Some(dir), _) => Some(dir.clone()),
+            (None, Some(_)) => Some(PathBuf::from(".")),
+            (None, None) => None,
+        }
+    }
+}
 
-    let md = html2md::parse_html(&body);
+async fn run(opts: &Opts) -> Result<()> {
+    let urls = opts.urls()?;
+    if urls.is_empty() {
+        return Err(anyhow!("没有要下载的url"));
+    }
+    let client = scrape::client()?;
+    let extract = opts.extract.extract()?;
 
-    fs::write(output, md.as_bytes())?;
-    println!("转换后的markdown文件为 {}", output); 
+    // 最多同时请求 concurrency 个url，结果仍然按url的顺序返回
+    let mut results = stream::iter(urls.iter())
+        .map(|url| {
+            let (client, extract) = (&client, &extract);
+            async move {
+                eprintln!("请求url {}", url);
+                (url, scrape::scrape(client, url, opts.format, extract).await)
+            }
+        })
+        .buffered(opts.concurrency);
 
+    // 进度和错误输出到标准错误，标准输出只有转换结果
+    let mut failed = Vec::new();
+    match opts.output_dir() {
+        // 每个页面写到单独的文件，下载完一个写一个
+        Some(dir) => {
+            fs::create_dir_all(&dir).with_context(|| format!("创建目录 {} 失败", dir.display()))?;
+            let names = batch::file_names(&urls, opts.format.extension());
+            let mut names = names.iter();
+            while let Some((url, result)) = results.next().await {
+                let path = dir.join(names.next().unwrap());
+                let result = result.and_then(|doc| {
+                    fs::write(&path, doc).with_context(|| format!("写入 {} 失败", path.display()))
+                });
+                match result {
+                    Ok(()) => eprintln!("{} => {}", url, path.display()),
+                    Err(e) => {
+                        eprintln!("下载错误: {:#}", e);
+                        failed.push(url);
+                    }
+                }
+            }
+        }
+        // 所有页面按顺序合并到一个文件或者标准输出
+        None => {
+            let mut docs = Vec::new();
+            while let Some((url, result)) = results.next().await {
+                match result {
+                    Ok(doc) => docs.push(doc),
+                    Err(e) => {
+                        eprintln!("下载错误: {:#}", e);
+                        failed.push(url);
+                    }
+                }
+            }
+
+            // 全部失败时不创建输出文件
+            if !docs.is_empty() {
+                let mut content = docs.join("\n");
+                if !content.ends_with('\n') {
+                    content.push('\n');
+                }
+
+                match opts.output {
+                    Some(ref path) => {
+                        fs::write(path, content).with_context(|| format!("写入 {} 失败", path.display()))?;
+                        eprintln!("转换后的文件为 {}", path.display());
+                    }
+                    None => io::stdout().lock().write_all(content.as_bytes())?,
+                }
+            }
+        }
+    }
+
+    // 使用 --input 或者多个url时最后汇总一次，方便在大量输出里找到失败的url
+    if opts.input.is_some() || urls.len() > 1 {
+        eprintln!("完成 {} 个url，成功 {} 个，失败 {} 个", urls.len(), urls.len() - failed.len(), failed.len());
+        for url in failed.iter() {
+            eprintln!("  失败: {}", url);
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow!("{} 个url下载失败", failed.len()));
+    }
     Ok(())
 }
 
-fn main() {
-    rust_html_to_md();
+async fn crawl(args: &Crawl) -> Result<()> {
+    let start: Url = args.url.parse()?;
+    let scope = crawl::Scope::new(&start, args.domain.as_deref(), args.prefix.as_deref())?;
 
-    let download_rst = rust_html_to_md_error();
+    let mut crawler = crawl::Crawler::new(scrape::client()?, scope, args.format, args.output_dir.clone());
+    crawler.extract = args.extract.extract()?;
+    crawler.max_depth = args.max_depth;
+    crawler.max_pages = args.max_pages;
+    crawler.delay = Duration::from_secs_f64(args.delay);
+    let summary = crawler.run(&start).await?;
+
+    eprintln!(
+        "抓取完成，保存 {} 个页面，失败 {} 个，robots.txt 不允许 {} 个",
+        summary.saved,
+        summary.failed.len(),
+        summary.blocked
+    );
+    for url in summary.failed.iter() {
+        eprintln!("  失败: {}", url);
+    }
+
+    // 个别页面失败只在汇总里列出，一个页面都没保存时才算失败
+    if summary.saved == 0 {
+        return Err(anyhow!("没有保存任何页面"));
+    }
+    Ok(())
+}
 
-    // 用match匹配返回的Result结果
-    let rst = match download_rst {
-        Ok(()) => (),
-        Err(error) => panic!("下载错误: {:?}", error),
+#[tokio::main]
+async fn main() {
+    let opts = Opts::parse();
+    let result = match opts.command {
+        Some(Command::Crawl(ref args)) => crawl(args).await,
+        None => run(&opts).await,
     };
 
-    rst
+    // 出错时打印可读的错误信息，并以非0状态码退出
+    if let Err(e) = result {
+        eprintln!("错误: {:#}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Serialize;
+
+// 页面的元数据，输出为 markdown 开头的 YAML front matter 或者json的 metadata 字段
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Metadata {
+    // 下载的url，跟随重定向后的地址
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canonical: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // OpenGraph 标签，去掉 og: 前缀，例如 og:image => image，同名的只保留第一个
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub open_graph: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    // 每一项按顺序尝试多个来源，取第一个非空的
+    pub fn extract(html: &str, url: &Url) -> Self {
+        let doc = Html::parse_document(html);
+        let first = |selectors: &[(&str, &str)]| {
+            selectors.iter().find_map(|(css, attr)| {
+                let selector = Selector::parse(css).unwrap();
+                doc.select(&selector).find_map(|e| {
+                    let value = match *attr {
+                        "text" => e.text().collect::<String>(),
+                        attr => e.value().attr(attr)?.to_string(),
+                    };
+                    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                    (!value.is_empty()).then_some(value)
+                })
+            })
+        };
+
+        let og = Selector::parse("meta[property^='og:'][content]").unwrap();
+        let mut open_graph = BTreeMap::new();
+        for e in doc.select(&og) {
+            let key = e.value().attr("property").unwrap_or_default().trim_start_matches("og:");
+            let value = e.value().attr("content").unwrap_or_default().trim();
+            if !key.is_empty() && !value.is_empty() {
+                open_graph.entry(key.to_string()).or_insert_with(|| value.to_string());
+            }
+        }
+
+        Self {
+            url: url.to_string(),
+            title: first(&[("head title", "text"), ("meta[property='og:title']", "content"), ("h1", "text")]),
+            description: first(&[
+                ("meta[name='description']", "content"),
+                ("meta[property='og:description']", "content"),
+            ]),
+            // 相对地址按页面url转成绝对url
+            canonical: first(&[("link[rel~='canonical']", "href"), ("meta[property='og:url']", "content")])
+                .and_then(|href| url.join(&href).ok())
+                .map(|url| url.to_string()),
+            author: first(&[
+                ("meta[name='author']", "content"),
+                ("meta[property='article:author']", "content"),
+                ("[rel~='author']", "text"),
+                ("[itemprop='author']", "text"),
+            ]),
+            published: first(&[
+                ("meta[property='article:published_time']", "content"),
+                ("meta[name='date']", "content"),
+                ("meta[itemprop='datePublished']", "content"),
+                ("time[itemprop='datePublished']", "datetime"),
+            ]),
+            language: first(&[
+                ("html", "lang"),
+                ("meta[http-equiv='content-language']", "content"),
+                ("meta[property='og:locale']", "content"),
+            ]),
+            open_graph,
+        }
+    }
+
+    // --- 包起来的 YAML，放在 markdown 开头
+    pub fn front_matter(&self) -> String {
+        let yaml = serde_yaml::to_string(self).expect("metadata is always serializable");
+        format!("---\n{}---\n\n", yaml)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_works() {
+        let html = r#"<html lang="en"><head>
+            <title> Ownership -
+              The Rust Book </title>
+            <meta name="description" content="Understanding ownership">
+            <meta property="og:title" content="Ownership">
+            <meta property="og:image" content="https://a.com/1.png">
+            <meta property="og:image" content="https://a.com/2.png">
+            <meta property="article:published_time" content="2024-05-01T08:00:00Z">
+            <link rel="canonical" href="/book/ownership.html">
+        </head><body><h1>Ownership</h1><a rel="author" href="/me">Steve: the author</a></body></html>"#;
+        let url = Url::parse("https://a.com/book/ch04.html?x=1").unwrap();
+        let meta = Metadata::extract(html, &url);
+
+        assert_eq!(meta.title.as_deref(), Some("Ownership - The Rust Book"));
+        assert_eq!(meta.canonical.as_deref(), Some("https://a.com/book/ownership.html"));
+        assert_eq!(meta.author.as_deref(), Some("Steve: the author"));
+        assert_eq!(
+            meta.front_matter(),
+            "---
+url: https://a.com/book/ch04.html?x=1
+title: Ownership - The Rust Book
+description: Understanding ownership
+canonical: https://a.com/book/ownership.html
+author: 'Steve: the author'
+published: 2024-05-01T08:00:00Z
+language: en
+open_graph:
+  image: https://a.com/1.png
+  title: Ownership
+---
+
+"
+        );
+
+        // 没有的字段不输出
+        let meta = Metadata::extract("<p>hi</p>", &url);
+        assert_eq!(meta.front_matter(), "---\nurl: https://a.com/book/ch04.html?x=1\n---\n\n");
+    }
+}
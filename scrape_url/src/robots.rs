@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+// 解析后的 robots.txt，只保留适用于本程序的那一组规则
+// 规则见 RFC 9309：最长匹配的规则生效，长度相同时 Allow 优先
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Robots {
+    // (是否允许, 路径模式)
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+}
+
+// 一组 User-agent 和它们共用的规则
+#[derive(Debug, Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    // 允许抓取所有页面，robots.txt 不存在时使用
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    // 禁止抓取所有页面，robots.txt 暂时无法访问时使用
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![(false, "/".to_string())],
+            crawl_delay: None,
+        }
+    }
+
+    // 优先使用名字匹配 agent 的分组，没有时使用 * 分组
+    // 按 RFC 9309 只比较产品名（agent 中 / 之前的部分），不区分大小写，匹配的多个分组合并规则
+    pub fn parse(content: &str, agent: &str) -> Self {
+        let product = agent.split('/').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let mut groups: Vec<Group> = Vec::new();
+        // 连续的 User-agent 行属于同一组
+        let mut in_agents = false;
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            if key == "user-agent" {
+                if !in_agents {
+                    groups.push(Group::default());
+                    in_agents = true;
+                }
+                groups.last_mut().unwrap().agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            in_agents = false;
+
+            // 第一个 User-agent 之前的规则不属于任何分组
+            let group = match groups.last_mut() {
+                Some(group) => group,
+                None => continue,
+            };
+            match key.as_str() {
+                // 空的 Disallow 表示允许所有
+                "allow" | "disallow" if !value.is_empty() => group.rules.push((key == "allow", value.to_string())),
+                "crawl-delay" => group.crawl_delay = value.parse::<f64>().ok().map(Duration::from_secs_f64),
+                _ => {}
+            }
+        }
+
+        let mut matched: Vec<&Group> = groups.iter().filter(|g| g.agents.contains(&product)).collect();
+        if matched.is_empty() {
+            matched = groups.iter().filter(|g| g.agents.iter().any(|a| a == "*")).collect();
+        }
+        Self {
+            rules: matched.iter().flat_map(|g| g.rules.iter().cloned()).collect(),
+            crawl_delay: matched.iter().find_map(|g| g.crawl_delay),
+        }
+    }
+
+    // path 包含查询参数，例如 /search?q=rust
+    // is_none_or 需要 rust 1.82，这里用 map_or 兼容旧版本
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map_or(true, |(allow, _)| *allow)
+    }
+}
+
+// 支持 * 匹配任意字符，结尾的 $ 表示必须匹配到路径末尾
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        // 锚定时最后一段必须出现在末尾，否则取第一次出现的位置
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn robots_works() {
+        let content = "\
+# 注释
+User-agent: *
+Disallow: /private/
+Allow: /private/public.html
+Disallow: /*.pdf$
+Crawl-delay: 2
+
+User-agent: googlebot
+User-agent: scrape_url
+Disallow: /
+Allow: /docs/
+Crawl-delay: 0.5
+";
+        let robots = Robots::parse(content, "Other/1.0");
+        assert!(robots.is_allowed("/"));
+        assert!(!robots.is_allowed("/private/a.html"));
+        assert!(robots.is_allowed("/private/public.html"));
+        assert!(!robots.is_allowed("/files/book.pdf"));
+        assert!(robots.is_allowed("/files/book.pdf?download=1"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+
+        // 名字匹配的分组优先
+        let robots = Robots::parse(content, "scrape_url/0.1.0");
+        assert!(!robots.is_allowed("/private/public.html"));
+        assert!(robots.is_allowed("/docs/index.html"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_millis(500)));
+
+        // 产品名不区分大小写，只是包含产品名的分组不算匹配
+        let content = "\
+User-agent: scrape
+Disallow: /
+
+User-agent: Scrape_URL
+Disallow: /a/
+
+User-agent: *
+Disallow: /b/
+
+user-agent: scrape_url
+Disallow: /c/
+";
+        let robots = Robots::parse(content, "scrape_url/0.1.0");
+        assert!(robots.is_allowed("/"));
+        assert!(!robots.is_allowed("/a/1.html"));
+        assert!(robots.is_allowed("/b/1.html"));
+        assert!(!robots.is_allowed("/c/1.html"));
+        let robots = Robots::parse(content, "my_scrape_url_bot/1.0");
+        assert!(robots.is_allowed("/a/1.html"));
+        assert!(!robots.is_allowed("/b/1.html"));
+
+        assert!(Robots::parse("User-agent: *\nDisallow:\n", "scrape_url").is_allowed("/a"));
+        assert!(Robots::parse("", "scrape_url").is_allowed("/a"));
+        assert!(!Robots::disallow_all().is_allowed("/a"));
+    }
+}
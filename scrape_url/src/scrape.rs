@@ -0,0 +1,246 @@
+use std::{collections::HashSet, str::FromStr, time::Duration};
+use anyhow::{anyhow, Context, Result};
+use reqwest::{header::CONTENT_TYPE, Client, Url};
+use scraper::{Html, Node};
+use serde::Serialize;
+
+use crate::{crawl, extract::Extract, metadata::Metadata};
+
+// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // html转成markdown
+    Markdown,
+    // 原样输出下载的html
+    Html,
+    // {metadata, markdown, links}，每个页面一行
+    Json,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 3] = ["markdown", "html", "json"];
+
+    // 每个页面单独保存时使用的扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "markdown" | "md" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!("不支持的格式 {}，可选 {}", s, Format::NAMES.join("、"))),
+        }
+    }
+}
+
+// 请求时使用的 User-Agent，也用来匹配 robots.txt 里的规则
+pub const USER_AGENT: &str = concat!("scrape_url/", env!("CARGO_PKG_VERSION"));
+
+// 所有url共用一个客户端，复用连接
+pub fn client() -> Result<Client> {
+    let client = Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    Ok(client)
+}
+
+// 下载到的页面
+#[derive(Debug)]
+pub struct Page {
+    // 跟随重定向后的url
+    pub url: Url,
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+impl Page {
+    // 没有 Content-Type 时当作html处理
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn is_html(&self) -> bool {
+        self.content_type.as_deref().map_or(true, |t| t.starts_with("text/html") || t.starts_with("application/xhtml+xml"))
+    }
+}
+
+// 下载页面，状态码不是2xx时返回错误
+pub async fn fetch_page(client: &Client, url: &str) -> Result<Page> {
+    let resp = client.get(url).send().await.with_context(|| format!("请求 {} 失败", url))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(anyhow!("{} 返回了 {}", url, status));
+    }
+
+    let final_url = resp.url().clone();
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_ascii_lowercase());
+    let body = resp.text().await.with_context(|| format!("读取 {} 的内容失败", url))?;
+    Ok(Page {
+        url: final_url,
+        content_type,
+        body,
+    })
+}
+
+// --format json 输出的内容
+#[derive(Debug, Serialize)]
+pub struct Document {
+    pub metadata: Metadata,
+    // 正文转换成的markdown，不带 front matter
+    pub markdown: String,
+    // 整个页面里的链接，去掉重复的
+    pub links: Vec<String>,
+}
+
+// 先取出正文，再按格式转换下载的html，url 用来把相对链接转成绝对url
+// markdown 开头加上元数据的 front matter，html 原样输出正文
+pub fn convert(html: &str, url: &Url, format: Format, extract: &Extract) -> Result<String> {
+    let content = extract.apply(html)?;
+    let base = crawl::base_url(&Html::parse_document(html), url);
+    let markdown = || html2md::parse_html(&absolute_links(&content, &base));
+    Ok(match format {
+        Format::Markdown => {
+            let metadata = Metadata::extract(html, url);
+            format!("{}{}", metadata.front_matter(), markdown())
+        }
+        Format::Html => content,
+        Format::Json => {
+            let mut seen = HashSet::new();
+            let links = crawl::links(html, url)
+                .into_iter()
+                .map(|link| link.to_string())
+                .filter(|link| seen.insert(link.clone()))
+                .collect();
+            let doc = Document {
+                metadata: Metadata::extract(html, url),
+                markdown: markdown(),
+                links,
+            };
+            serde_json::to_string(&doc)?
+        }
+    })
+}
+
+// 把 href、src 中的相对链接转成绝对url，保存下来的markdown里链接和图片仍然能打开
+fn absolute_links(html: &str, base: &Url) -> String {
+    let mut doc = Html::parse_document(html);
+    for node in doc.tree.values_mut() {
+        if let Node::Element(element) = node {
+            for (name, value) in element.attrs.iter_mut() {
+                if matches!(&*name.local, "href" | "src") {
+                    if let Ok(url) = base.join(value.trim()) {
+                        *value = url.as_str().into();
+                    }
+                }
+            }
+        }
+    }
+    doc.html()
+}
+
+// 下载并转换一个url
+pub async fn scrape(client: &Client, url: &str, format: Format, extract: &Extract) -> Result<String> {
+    let page = fetch_page(client, url).await?;
+    convert(&page.body, &page.url, format, extract).with_context(|| format!("转换 {} 失败", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    // 只处理一个请求的http服务，返回固定的响应
+    fn serve_once(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf);
+            let resp = format!(
+                "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(resp.as_bytes()).unwrap();
+        });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn scrape_works() {
+        let client = client().unwrap();
+
+        let url = serve_once("200 OK", "<h1>Rust</h1><p>A <a href=\"/learn\">language</a></p>");
+        let md = scrape(&client, &url, Format::Markdown, &Extract::Full).await.unwrap();
+        let front_matter = format!("---\nurl: {}\ntitle: Rust\n---\n\n", url);
+        assert!(md.starts_with(&format!("{}Rust\n==========", front_matter)), "{}", md);
+        assert!(md.contains(&format!("[language]({}learn)", url)), "{}", md);
+
+        // json 里的链接和markdown里的链接都是绝对url
+        let url = serve_once("200 OK", "<title>Rust</title><p>A <a href=\"/learn\">language</a></p>");
+        let json = scrape(&client, &url, Format::Json, &Extract::Auto).await.unwrap();
+        let doc: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(doc["metadata"]["title"], "Rust");
+        assert_eq!(doc["markdown"], format!("A [language]({}learn)", url));
+        assert_eq!(doc["links"], serde_json::json!([format!("{}learn", url)]));
+        assert!(!json.contains('\n'));
+
+        let url = serve_once("200 OK", "<p>hi</p>");
+        assert_eq!(scrape(&client, &url, Format::Html, &Extract::Full).await.unwrap(), "<p>hi</p>");
+
+        // 非2xx和连接失败都返回错误，而不是panic
+        let url = serve_once("404 Not Found", "missing");
+        let err = scrape(&client, &url, Format::Markdown, &Extract::Full).await.unwrap_err();
+        assert_eq!(err.to_string(), format!("{} 返回了 404 Not Found", url));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        assert!(fetch_page(&client, &closed).await.is_err());
+    }
+
+    #[test]
+    fn absolute_links_works() {
+        let page = Url::parse("https://a.com/book/ch01.html").unwrap();
+        let html = r##"<p><a href="ch02.html">2</a> <a href="#top">top</a> <a href="mailto:a@a.com">mail</a>
+            <img src="/img/logo.png" alt="logo"> <a href="https://b.com/x">b</a></p>"##;
+        let md = html2md::parse_html(&absolute_links(html, &page));
+        assert!(md.contains("[2](https://a.com/book/ch02.html)"), "{}", md);
+        assert!(md.contains("[top](https://a.com/book/ch01.html#top)"), "{}", md);
+        assert!(md.contains("[mail](mailto:a@a.com)"), "{}", md);
+        assert!(md.contains("![logo](https://a.com/img/logo.png)"), "{}", md);
+        assert!(md.contains("[b](https://b.com/x)"), "{}", md);
+
+        // 有 <base> 时按 <base> 补全
+        let html = r#"<head><base href="/docs/"></head><a href="x">x</a>"#;
+        let md = convert(html, &page, Format::Markdown, &Extract::Full).unwrap();
+        assert!(md.ends_with("[x](https://a.com/docs/x)"), "{}", md);
+    }
+
+    #[test]
+    fn format_works() {
+        assert_eq!("md".parse::<Format>().unwrap(), Format::Markdown);
+        assert_eq!("html".parse::<Format>().unwrap(), Format::Html);
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert!("pdf".parse::<Format>().is_err());
+    }
+}